@@ -0,0 +1,100 @@
+use rand::{Rng, StdRng};
+use std::cell::RefCell;
+use std::cmp;
+
+use config::*;
+use rect::*;
+use map::*;
+use object::*;
+use data::GameData;
+
+/// Splits the whole map in two, again and again, until the parts are about
+/// room sized. Every part gets a room and the two halves of every split are
+/// joined with a tunnel, so rooms are spread evenly over the map.
+pub struct BspGenerator;
+
+impl MapGenerator for BspGenerator {
+    fn generate(&self, objects: &mut Vec<RefCell<Object>>, level: u32, data: &GameData, rng: &mut StdRng) -> Map {
+        let config = &data.config;
+        let mut map = new_map(config);
+        let mut rooms = vec![];
+
+        dig(Rect::new(0, 0, config.map_width, config.map_height), &mut map, &mut rooms, config, rng);
+        populate_rooms(&rooms, &map, objects, level, data, rng);
+
+        map
+    }
+}
+
+/// Smallest part that still fits a room together with its walls.
+fn min_leaf_size(config: &Config) -> i32 {
+    config.room_min_size + 1
+}
+
+fn dig(area: Rect, map: &mut Map, rooms: &mut Vec<Rect>, config: &Config, rng: &mut StdRng) {
+    match split(area, config, rng) {
+        Some((first, second)) => {
+            let first_start = rooms.len();
+            dig(first, map, rooms, config, rng);
+            let second_start = rooms.len();
+            dig(second, map, rooms, config, rng);
+
+            // connect any room of one half to any room of the other
+            let from = rooms[rng.gen_range(first_start, second_start)].center();
+            let to = rooms[rng.gen_range(second_start, rooms.len())].center();
+            create_l_tunnel(from, to, map, rng);
+        },
+        None => {
+            let room = room_inside(area, config, rng);
+            create_room(room, map);
+            rooms.push(room);
+        }
+    }
+}
+
+/// Cuts the area in two, returns None when it is small enough to be a leaf.
+fn split(area: Rect, config: &Config, rng: &mut StdRng) -> Option<(Rect, Rect)> {
+    let w = area.x2 - area.x1;
+    let h = area.y2 - area.y1;
+    if w <= config.bsp_max_leaf_size && h <= config.bsp_max_leaf_size {
+        return None;
+    }
+
+    let min_leaf = min_leaf_size(config);
+    let can_split_vertically = w >= 2 * min_leaf;
+    let can_split_horizontally = h >= 2 * min_leaf;
+    let vertical = match (can_split_vertically, can_split_horizontally) {
+        (false, false) => return None,
+        (true, false) => true,
+        (false, true) => false,
+        // prefer cutting across the longer side
+        (true, true) => {
+            if w as f32 > h as f32 * 1.25 {
+                true
+            } else if h as f32 > w as f32 * 1.25 {
+                false
+            } else {
+                rng.gen()
+            }
+        },
+    };
+
+    if vertical {
+        let cut = area.x1 + rng.gen_range(min_leaf, w - min_leaf + 1);
+        Some((Rect { x2: cut, ..area }, Rect { x1: cut, ..area }))
+    } else {
+        let cut = area.y1 + rng.gen_range(min_leaf, h - min_leaf + 1);
+        Some((Rect { y2: cut, ..area }, Rect { y1: cut, ..area }))
+    }
+}
+
+/// A random room that fits in the area, walls included.
+fn room_inside(area: Rect, config: &Config, rng: &mut StdRng) -> Rect {
+    let w = area.x2 - area.x1;
+    let h = area.y2 - area.y1;
+    let room_w = rng.gen_range(config.room_min_size, cmp::min(config.room_max_size, w - 1) + 1);
+    let room_h = rng.gen_range(config.room_min_size, cmp::min(config.room_max_size, h - 1) + 1);
+    let x = rng.gen_range(area.x1, area.x2 - room_w);
+    let y = rng.gen_range(area.y1, area.y2 - room_h);
+    Rect::new(x, y, room_w, room_h)
+}
@@ -0,0 +1,65 @@
+use std::cmp;
+
+use map::*;
+
+/// The part of the map shown on the screen. It follows the player around,
+/// so levels can be bigger than the console.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    // map position of the top left corner of the screen
+    pub x: i32,
+    pub y: i32,
+    // size of the part of the screen showing the map
+    width: i32,
+    height: i32,
+    // size of the map followed last
+    map_width: i32,
+    map_height: i32,
+}
+
+impl Camera {
+    pub fn new(width: i32, height: i32) -> Self {
+        Camera { x: 0, y: 0, width: width, height: height, map_width: 0, map_height: 0 }
+    }
+
+    /// Centers the camera on the given position of the map, without showing
+    /// anything past the map edges. Returns true if the camera moved.
+    pub fn follow(&mut self, x: i32, y: i32, map: &Map) -> bool {
+        let (map_width, map_height) = map_size(map);
+        let new_x = clamp(x - self.width / 2, 0, map_width - self.width);
+        let new_y = clamp(y - self.height / 2, 0, map_height - self.height);
+        let moved = (new_x, new_y, map_width, map_height) != (self.x, self.y, self.map_width, self.map_height);
+        self.x = new_x;
+        self.y = new_y;
+        self.map_width = map_width;
+        self.map_height = map_height;
+        moved
+    }
+
+    /// Screen position of a map tile, None if it is out of view.
+    pub fn to_screen(&self, x: i32, y: i32) -> Option<(i32, i32)> {
+        let (screen_x, screen_y) = (x - self.x, y - self.y);
+        if screen_x < 0 || screen_y < 0 || screen_x >= self.width || screen_y >= self.height {
+            None
+        } else {
+            Some((screen_x, screen_y))
+        }
+    }
+
+    /// Map tile shown at a screen position, None if it is not on the map.
+    pub fn to_map(&self, screen_x: i32, screen_y: i32) -> Option<(i32, i32)> {
+        if screen_x < 0 || screen_y < 0 || screen_x >= self.width || screen_y >= self.height {
+            return None;
+        }
+        let (x, y) = (screen_x + self.x, screen_y + self.y);
+        if x >= self.map_width || y >= self.map_height {
+            None
+        } else {
+            Some((x, y))
+        }
+    }
+}
+
+fn clamp(value: i32, min: i32, max: i32) -> i32 {
+    cmp::max(min, cmp::min(value, max))
+}
@@ -0,0 +1,144 @@
+use rand::{Rng, StdRng};
+use std::cell::RefCell;
+
+use config::*;
+use tile::*;
+use map::*;
+use rect::Rect;
+use object::*;
+use data::GameData;
+
+/// Organic caves: the map starts as random noise and is smoothed with
+/// cellular automata, then every pocket not connected to the biggest
+/// cave is walled up.
+pub struct CaveGenerator;
+
+impl MapGenerator for CaveGenerator {
+    fn generate(&self, objects: &mut Vec<RefCell<Object>>, level: u32, data: &GameData, rng: &mut StdRng) -> Map {
+        let config = &data.config;
+        let (map, cave) = make_cave(config, rng);
+
+        // the player starts anywhere in the cave, the stairs are as far as it gets
+        let start = *rng.choose(&cave).unwrap();
+        objects[PLAYER].borrow_mut().set_pos(start.0, start.1);
+        let reachable = flood_fill(&map, start);
+
+        let area_size = config.cave_area_size;
+        let mut x = 0;
+        while x < config.map_width {
+            let mut y = 0;
+            while y < config.map_height {
+                let area: Vec<_> = cave.iter().cloned().filter(|&(tile_x, tile_y)| {
+                    tile_x >= x && tile_x < x + area_size && tile_y >= y && tile_y < y + area_size
+                }).collect();
+                // skip the thin bits, they would get as much as a whole room
+                if area.len() as i32 >= area_size * area_size / 4 {
+                    place_objects_on(&area, &map, objects, level, data, rng);
+                }
+                y += area_size;
+            }
+            x += area_size;
+        }
+
+        let (stairs_x, stairs_y) = reachable[reachable.len() - 1];
+        place_stairs(stairs_x, stairs_y, objects);
+
+        map
+    }
+}
+
+/// Carves the cave. Noise with too many walls can smooth into a cave too
+/// small to play in, it is tried again and in the end a room is dug out in
+/// the middle. Returns the map and the tiles of the cave.
+fn make_cave(config: &Config, rng: &mut StdRng) -> (Map, Vec<(i32, i32)>) {
+    let min_cave_size = (config.room_min_size * config.room_min_size) as usize;
+    let mut attempts = 1;
+    loop {
+        let mut map = new_map(config);
+
+        // random noise, the map border always stays a wall
+        for x in 1..(config.map_width - 1) {
+            for y in 1..(config.map_height - 1) {
+                if rng.gen_range(0, 100) >= config.cave_wall_percent {
+                    map[x as usize][y as usize] = Tile::empty();
+                }
+            }
+        }
+
+        for _ in 0..config.cave_smoothing_steps {
+            map = smooth(&map);
+        }
+
+        let mut cave = remove_pockets(&mut map);
+        if cave.len() < min_cave_size && attempts >= config.max_map_attempts {
+            let room = Rect::new(config.map_width / 2 - config.room_min_size / 2, config.map_height / 2 - config.room_min_size / 2,
+                                 config.room_min_size, config.room_min_size);
+            create_room(room, &mut map);
+            cave = remove_pockets(&mut map);
+        }
+        if cave.len() >= min_cave_size || attempts >= config.max_map_attempts {
+            return (map, cave);
+        }
+        attempts += 1;
+    }
+}
+
+/// One step of the automaton: a tile turns into a wall when most of its
+/// neighbours are walls, and into floor otherwise.
+fn smooth(map: &Map) -> Map {
+    let (width, height) = map_size(map);
+    let mut result = map.clone();
+    for x in 1..(width - 1) {
+        for y in 1..(height - 1) {
+            let walls = count_walls_around(map, x, y);
+            result[x as usize][y as usize] = if walls >= 5 {
+                Tile::wall()
+            } else {
+                Tile::empty()
+            };
+        }
+    }
+    result
+}
+
+/// Number of walls in the 3x3 square centered on the tile, the tile included.
+fn count_walls_around(map: &Map, x: i32, y: i32) -> i32 {
+    let mut walls = 0;
+    for nx in (x - 1)..(x + 2) {
+        for ny in (y - 1)..(y + 2) {
+            if map[nx as usize][ny as usize].blocked {
+                walls += 1;
+            }
+        }
+    }
+    walls
+}
+
+/// Keeps the biggest connected cave and fills all the other ones,
+/// returns the tiles of the cave that is left.
+fn remove_pockets(map: &mut Map) -> Vec<(i32, i32)> {
+    let (width, height) = map_size(map);
+    let mut visited = vec![vec![false; height as usize]; width as usize];
+    let mut caves = vec![];
+
+    for x in 0..width {
+        for y in 0..height {
+            if !visited[x as usize][y as usize] && !map[x as usize][y as usize].blocked {
+                let cave = flood_fill(map, (x, y));
+                for &(cave_x, cave_y) in &cave {
+                    visited[cave_x as usize][cave_y as usize] = true;
+                }
+                caves.push(cave);
+            }
+        }
+    }
+
+    caves.sort_by(|a, b| b.len().cmp(&a.len()));
+    for cave in caves.iter().skip(1) {
+        for &(x, y) in cave {
+            map[x as usize][y as usize] = Tile::wall();
+        }
+    }
+
+    caves.into_iter().next().unwrap_or(vec![])
+}
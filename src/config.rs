@@ -1,55 +1,435 @@
+use std::fs::File;
+use std::io::{self, Read};
+use rustc_serialize::Decodable;
+use rustc_serialize::json::{self, Json};
 use tcod::colors::Color;
 use tcod::map::FovAlgorithm;
 
-pub const SCREEN_WIDTH: i32 = 80;
-pub const SCREEN_HEIGHT: i32 = 50;
+use data::DataError;
 
-// sizes and coordinates for the GUI
-pub const BAR_WIDTH: i32 = 20;
-pub const PANEL_HEIGHT: i32 = 7;
-pub const PANEL_Y: i32 = SCREEN_HEIGHT - PANEL_HEIGHT;
+// the settings file, every setting left out keeps its default value
+pub const CONFIG_FILE: &'static str = "config.json";
 
-pub const MSG_X: i32 = BAR_WIDTH + 2;
-pub const MSG_WIDTH: i32 = SCREEN_WIDTH - BAR_WIDTH - 2;
-pub const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
+// messages kept in the log, older ones are forgotten
+pub const MSG_HISTORY: usize = 500;
 pub const INVENTORY_WIDTH: i32 = 50;
 pub const HELP_WIDTH: i32 = 50;
+// the panel describing what is under the cursor in look mode
+pub const LOOK_WIDTH: i32 = 30;
 
-// map size
-pub const MAP_WIDTH: i32 = 80;
-pub const MAP_HEIGHT: i32 = 43;
-
-pub const ROOM_MAX_SIZE: i32 = 10;
-pub const ROOM_MIN_SIZE: i32 = 6;
-pub const MAX_ROOMS: i32 = 30;
-
-pub const LIMIT_FPS: i32 = 20;
-
-pub const COLOR_DARK_WALL: Color = Color { r: 0, g: 0, b: 100 };
-pub const COLOR_LIGHT_WALL: Color = Color { r: 130, g: 110, b: 50 };
-pub const COLOR_DARK_GROUND: Color = Color { r: 50, g: 50, b: 150 };
-pub const COLOR_LIGHT_GROUND: Color = Color { r: 200, g: 180, b: 50 };
-
-pub const FOV_ALGO: FovAlgorithm = FovAlgorithm::Basic;
-pub const FOV_LIGHT_WALLS: bool = true;
-pub const TORCH_RADIUS: i32 = 10;
+// data files describing the dungeon contents
+pub const MONSTERS_FILE: &'static str = "data/monsters.json";
+pub const ITEMS_FILE: &'static str = "data/items.json";
+pub const KEYS_FILE: &'static str = "data/keys.json";
+// saved games go in this directory of the user's data directory, one file per slot
+pub const SAVE_DIR_NAME: &'static str = "rusty_roguelike";
+pub const SAVE_SLOTS: usize = 5;
+// where the game was saved before there were slots
+pub const OLD_SAVE_FILE: &'static str = "savegame";
+// the last game played is recorded there, to be replayed with --replay
+pub const REPLAY_FILE: &'static str = "last_game.replay";
 
 pub const MAX_INVENTORY_SIZE: i32 = 26;
 
+// time: every tick fighters gain their speed in energy, and act once they have enough
+pub const NORMAL_SPEED: i32 = 100;
+pub const ACTION_ENERGY: i32 = 100;
+pub const MOVE_COST: i32 = 100;
+pub const ATTACK_COST: i32 = 120;
+pub const WAIT_COST: i32 = 100;
+pub const PICK_UP_COST: i32 = 100;
+pub const DROP_COST: i32 = 50;
+pub const USE_ITEM_COST: i32 = 100;
+pub const STAIRS_COST: i32 = 100;
+
 // player's index in objects vector
 pub const PLAYER: usize = 0;
 
-// Spells
-pub const HEAL_AMOUNT: i32 = 40;
-pub const LIGHTNING_DAMAGE: i32 = 40;
-pub const LIGHTNING_RANGE: i32 = 5;
-pub const CONFUSE_RANGE: i32 = 8;
-pub const CONFUSE_NUM_TURNS: i32 = 10;
-pub const FIREBALL_RADIUS: i32 = 3;
-pub const FIREBALL_DAMAGE: i32 = 25;
-
-// experience and level ups
-pub const LEVEL_UP_BASE: i32 = 200;
-pub const LEVEL_UP_FACTOR: i32 = 150;
 pub const LEVEL_SCREEN_WIDTH: i32 = 40;
 pub const CHARACTER_SCREEN_WIDTH: i32 = 30;
+pub const SAVE_SLOTS_WIDTH: i32 = 70;
+
+// the screen has to fit the biggest menus and the help
+const MIN_SCREEN_WIDTH: i32 = SAVE_SLOTS_WIDTH;
+const MIN_SCREEN_HEIGHT: i32 = 30;
+// the panel shows the health bar, the dungeon level and the status effects
+const MIN_PANEL_HEIGHT: i32 = 7;
+// more walls than that and the smoothing leaves hardly any cave to play in
+const MAX_CAVE_WALL_PERCENT: u32 = 55;
+
+/// The field of view algorithms of tcod, by the name used in the settings file.
+#[derive(Clone, Copy, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub enum Fov {
+    Basic,
+    Diamond,
+    Shadow,
+    Permissive0,
+    Permissive1,
+    Permissive2,
+    Permissive3,
+    Permissive4,
+    Permissive5,
+    Permissive6,
+    Permissive7,
+    Permissive8,
+    Restrictive,
+}
+
+impl Fov {
+    pub fn algorithm(self) -> FovAlgorithm {
+        match self {
+            Fov::Basic => FovAlgorithm::Basic,
+            Fov::Diamond => FovAlgorithm::Diamond,
+            Fov::Shadow => FovAlgorithm::Shadow,
+            Fov::Permissive0 => FovAlgorithm::Permissive0,
+            Fov::Permissive1 => FovAlgorithm::Permissive1,
+            Fov::Permissive2 => FovAlgorithm::Permissive2,
+            Fov::Permissive3 => FovAlgorithm::Permissive3,
+            Fov::Permissive4 => FovAlgorithm::Permissive4,
+            Fov::Permissive5 => FovAlgorithm::Permissive5,
+            Fov::Permissive6 => FovAlgorithm::Permissive6,
+            Fov::Permissive7 => FovAlgorithm::Permissive7,
+            Fov::Permissive8 => FovAlgorithm::Permissive8,
+            Fov::Restrictive => FovAlgorithm::Restrictive,
+        }
+    }
+}
+
+/// The settings that can be tuned without recompiling, read from the
+/// settings file at startup.
+#[derive(Clone, Copy, Debug, RustcEncodable, RustcDecodable)]
+pub struct Config {
+    pub screen_width: i32,
+    pub screen_height: i32,
+    pub limit_fps: i32,
+
+    // sizes for the GUI
+    pub bar_width: i32,
+    pub panel_height: i32,
+
+    // map size, can be bigger than the part shown by the camera
+    pub map_width: i32,
+    pub map_height: i32,
+
+    pub room_max_size: i32,
+    pub room_min_size: i32,
+    pub max_rooms: i32,
+    // maps that fail validation and caves too small to play in are generated
+    // again this many times, then repaired
+    pub max_map_attempts: i32,
+    // binary space partition stops splitting areas smaller than this
+    pub bsp_max_leaf_size: i32,
+    // cellular automata caves
+    pub cave_wall_percent: u32,
+    pub cave_smoothing_steps: i32,
+    // caves are split in squares of this size, each gets objects like a room
+    pub cave_area_size: i32,
+
+    pub color_dark_wall: Color,
+    pub color_light_wall: Color,
+    pub color_dark_ground: Color,
+    pub color_light_ground: Color,
+
+    pub fov_algorithm: Fov,
+    pub fov_light_walls: bool,
+    pub torch_radius: i32,
+
+    // monsters don't look for paths longer than this and walk straight instead
+    pub monster_path_length: i32,
+
+    // auto-explore walks to the items it sees and picks them up
+    pub explore_pick_up: bool,
+
+    // turns a wounded fighter takes to heal completely by itself
+    pub full_regen_turns: i32,
+
+    // spells
+    pub heal_amount: i32,
+    pub lightning_damage: i32,
+    pub lightning_range: i32,
+    pub confuse_range: i32,
+    pub confuse_num_turns: i32,
+    pub fireball_radius: i32,
+    pub fireball_damage: i32,
+    // healing each turn
+    pub regeneration_amount: i32,
+    pub regeneration_turns: i32,
+    pub haste_turns: i32,
+    pub slow_range: i32,
+    pub slow_turns: i32,
+    pub blind_range: i32,
+    pub blind_turns: i32,
+    pub paralyse_range: i32,
+    pub paralyse_turns: i32,
+
+    // experience needed for the next level: base + level * factor
+    pub level_up_base: i32,
+    pub level_up_factor: i32,
+
+    // the game is saved every that many turns, and on each level change
+    pub autosave_turns: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            screen_width: 80,
+            screen_height: 50,
+            limit_fps: 20,
+
+            bar_width: 20,
+            panel_height: 7,
+
+            map_width: 100,
+            map_height: 60,
+
+            room_max_size: 10,
+            room_min_size: 6,
+            max_rooms: 50,
+            max_map_attempts: 3,
+            bsp_max_leaf_size: 16,
+            cave_wall_percent: 45,
+            cave_smoothing_steps: 5,
+            cave_area_size: 10,
+
+            color_dark_wall: Color { r: 0, g: 0, b: 100 },
+            color_light_wall: Color { r: 130, g: 110, b: 50 },
+            color_dark_ground: Color { r: 50, g: 50, b: 150 },
+            color_light_ground: Color { r: 200, g: 180, b: 50 },
+
+            fov_algorithm: Fov::Basic,
+            fov_light_walls: true,
+            torch_radius: 10,
+
+            monster_path_length: 30,
+
+            explore_pick_up: true,
+
+            full_regen_turns: 300,
+
+            heal_amount: 40,
+            lightning_damage: 40,
+            lightning_range: 5,
+            confuse_range: 8,
+            confuse_num_turns: 10,
+            fireball_radius: 3,
+            fireball_damage: 25,
+            regeneration_amount: 2,
+            regeneration_turns: 20,
+            haste_turns: 30,
+            slow_range: 8,
+            slow_turns: 20,
+            blind_range: 8,
+            blind_turns: 20,
+            paralyse_range: 5,
+            paralyse_turns: 5,
+
+            level_up_base: 200,
+            level_up_factor: 150,
+
+            autosave_turns: 100,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the settings file over the default settings. A missing file
+    /// just gives the defaults.
+    pub fn load(path: &str) -> Result<Config, DataError> {
+        let error = |problem: String| DataError { path: path.into(), problems: vec![problem] };
+
+        let mut contents = String::new();
+        match File::open(path) {
+            Ok(mut file) => {
+                try! { file.read_to_string(&mut contents).map_err(|e| error(e.to_string())) };
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(error(e.to_string())),
+        }
+        let settings = match try! { Json::from_str(&contents).map_err(|e| error(e.to_string())) } {
+            Json::Object(settings) => settings,
+            _ => return Err(error("the settings must be a JSON object".into())),
+        };
+
+        let config = try! { Config::with_settings(settings).map_err(|problems| {
+            DataError { path: path.into(), problems: problems }
+        }) };
+        let problems = config.problems();
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(DataError { path: path.into(), problems: problems })
+        }
+    }
+
+    /// The default settings with the given ones replaced. Each setting is
+    /// tried on its own first, so every bad one is reported by its name.
+    fn with_settings(settings: json::Object) -> Result<Config, Vec<String>> {
+        let defaults = match Json::from_str(&json::encode(&Config::default()).unwrap()) {
+            Ok(Json::Object(defaults)) => defaults,
+            _ => unreachable!(),
+        };
+
+        let mut problems = vec![];
+        let mut merged = defaults.clone();
+        for (name, value) in settings {
+            if !defaults.contains_key(&name) {
+                problems.push(format!("unknown setting \"{}\"", name));
+                continue;
+            }
+            let mut alone = defaults.clone();
+            alone.insert(name.clone(), value.clone());
+            match decode(alone) {
+                Ok(_) => { merged.insert(name, value); },
+                Err(e) => problems.push(format!("\"{}\": {}", name, e)),
+            }
+        }
+
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+        decode(merged).map_err(|e| vec![e.to_string()])
+    }
+
+    /// What is wrong with the settings, empty if they can be played with.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        {
+            let mut at_least = |name: &str, value: i32, min: i32| {
+                if value < min {
+                    problems.push(format!("{} must be at least {}, got {}", name, min, value));
+                }
+            };
+            at_least("screen_width", self.screen_width, MIN_SCREEN_WIDTH);
+            at_least("screen_height", self.screen_height, MIN_SCREEN_HEIGHT);
+            at_least("limit_fps", self.limit_fps, 1);
+            at_least("bar_width", self.bar_width, 1);
+            at_least("panel_height", self.panel_height, MIN_PANEL_HEIGHT);
+            at_least("room_min_size", self.room_min_size, 3);
+            at_least("room_max_size", self.room_max_size, self.room_min_size);
+            // rooms need a wall around them
+            at_least("map_width", self.map_width, self.room_max_size + 2);
+            at_least("map_height", self.map_height, self.room_max_size + 2);
+            at_least("max_rooms", self.max_rooms, 1);
+            at_least("max_map_attempts", self.max_map_attempts, 1);
+            at_least("bsp_max_leaf_size", self.bsp_max_leaf_size, self.room_min_size + 1);
+            at_least("cave_smoothing_steps", self.cave_smoothing_steps, 0);
+            at_least("cave_area_size", self.cave_area_size, 1);
+            at_least("torch_radius", self.torch_radius, 1);
+            at_least("monster_path_length", self.monster_path_length, 1);
+            at_least("full_regen_turns", self.full_regen_turns, 1);
+            at_least("heal_amount", self.heal_amount, 1);
+            at_least("lightning_damage", self.lightning_damage, 0);
+            at_least("lightning_range", self.lightning_range, 1);
+            at_least("confuse_range", self.confuse_range, 1);
+            at_least("confuse_num_turns", self.confuse_num_turns, 1);
+            at_least("fireball_radius", self.fireball_radius, 0);
+            at_least("fireball_damage", self.fireball_damage, 0);
+            at_least("regeneration_amount", self.regeneration_amount, 1);
+            at_least("regeneration_turns", self.regeneration_turns, 1);
+            at_least("haste_turns", self.haste_turns, 1);
+            at_least("slow_range", self.slow_range, 1);
+            at_least("slow_turns", self.slow_turns, 1);
+            at_least("blind_range", self.blind_range, 1);
+            at_least("blind_turns", self.blind_turns, 1);
+            at_least("paralyse_range", self.paralyse_range, 1);
+            at_least("paralyse_turns", self.paralyse_turns, 1);
+            at_least("level_up_base", self.level_up_base, 1);
+            at_least("level_up_factor", self.level_up_factor, 0);
+        }
+        // the messages are shown next to the health bar
+        if self.bar_width + 2 >= self.screen_width {
+            problems.push(format!("bar_width must be less than screen_width - 2, got {}", self.bar_width));
+        }
+        if self.panel_height >= self.screen_height {
+            problems.push(format!("panel_height must be less than screen_height, got {}", self.panel_height));
+        }
+        if self.cave_wall_percent > MAX_CAVE_WALL_PERCENT {
+            problems.push(format!("cave_wall_percent must be at most {}, got {}", MAX_CAVE_WALL_PERCENT,
+                                  self.cave_wall_percent));
+        }
+        if self.autosave_turns == 0 {
+            problems.push("autosave_turns must be at least 1, got 0".into());
+        }
+        problems
+    }
+
+    pub fn panel_y(&self) -> i32 {
+        self.screen_height - self.panel_height
+    }
+
+    pub fn msg_x(&self) -> i32 {
+        self.bar_width + 2
+    }
+
+    pub fn msg_width(&self) -> i32 {
+        self.screen_width - self.bar_width - 2
+    }
+
+    pub fn msg_height(&self) -> usize {
+        self.panel_height as usize - 1
+    }
+
+    /// Width of the part of the screen showing the map.
+    pub fn camera_width(&self) -> i32 {
+        self.screen_width
+    }
+
+    /// Height of the part of the screen showing the map.
+    pub fn camera_height(&self) -> i32 {
+        self.screen_height - self.panel_height
+    }
+}
+
+fn decode(settings: json::Object) -> Result<Config, json::DecoderError> {
+    let mut decoder = json::Decoder::new(Json::Object(settings));
+    Config::decode(&mut decoder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(text: &str) -> json::Object {
+        match Json::from_str(text) {
+            Ok(Json::Object(settings)) => settings,
+            _ => panic!("not a JSON object: {}", text),
+        }
+    }
+
+    #[test]
+    fn settings_replace_the_defaults() {
+        let config = Config::with_settings(settings(r#"{"map_width": 120, "fov_algorithm": "Shadow",
+                                                        "color_dark_wall": {"r": 1, "g": 2, "b": 3}}"#)).unwrap();
+        assert_eq!(config.map_width, 120);
+        assert_eq!(config.fov_algorithm, Fov::Shadow);
+        assert_eq!((config.color_dark_wall.r, config.color_dark_wall.g, config.color_dark_wall.b), (1, 2, 3));
+        assert_eq!(config.map_height, Config::default().map_height);
+    }
+
+    #[test]
+    fn every_bad_setting_is_reported() {
+        let problems = Config::with_settings(settings(r#"{"map_widht": 120, "torch_radius": "far",
+                                                          "fov_algorithm": "Nope", "map_height": 50}"#)).unwrap_err();
+        assert_eq!(problems.len(), 3);
+        assert!(problems.contains(&"unknown setting \"map_widht\"".to_string()));
+        assert!(problems.iter().any(|problem| problem.starts_with("\"torch_radius\"")));
+        assert!(problems.iter().any(|problem| problem.starts_with("\"fov_algorithm\"")));
+    }
+
+    #[test]
+    fn default_settings_have_no_problems() {
+        assert_eq!(Config::default().problems(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn settings_that_dont_make_sense_are_problems() {
+        let config = Config::with_settings(settings(r#"{"room_min_size": 12, "screen_width": 40,
+                                                        "cave_wall_percent": 100, "autosave_turns": 0}"#)).unwrap();
+        let problems = config.problems();
+        assert!(problems.contains(&format!("screen_width must be at least {}, got 40", MIN_SCREEN_WIDTH)));
+        assert!(problems.contains(&"room_max_size must be at least 12, got 10".to_string()));
+        assert!(problems.contains(&format!("cave_wall_percent must be at most {}, got 100", MAX_CAVE_WALL_PERCENT)));
+        assert!(problems.contains(&"autosave_turns must be at least 1, got 0".to_string()));
+    }
+}
@@ -0,0 +1,301 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use rustc_serialize::{json, Decodable};
+use tcod::colors::Color;
+
+use config::*;
+use map::Transition;
+use object::*;
+use keys::*;
+use effects::StatusEffect;
+
+/// An entry of a data file that can be checked after loading.
+trait DataEntry {
+    fn name(&self) -> &str;
+    fn problems(&self) -> Vec<String>;
+}
+
+/// A kind of monster, as described in the monsters data file.
+#[derive(Clone, Debug, RustcDecodable)]
+pub struct MonsterDef {
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    pub hp: i32,
+    pub defense: i32,
+    pub power: i32,
+    pub speed: i32,
+    pub xp: i32,
+    pub ai: Ai,
+    // given to whatever it hits, if any
+    pub hit_effect: Option<StatusEffect>,
+    // chance to appear from each dungeon level on, compared to the other monsters
+    pub spawn_weights: Vec<Transition>,
+}
+
+impl MonsterDef {
+    pub fn spawn(&self, x: i32, y: i32) -> Object {
+        let mut monster = Object::new(x, y, self.glyph, &self.name, self.color, true);
+        monster.fighter = Some(Fighter{
+            base_max_hp: self.hp, hp: self.hp, base_defense: self.defense, base_power: self.power, xp: self.xp,
+            base_speed: self.speed, energy: 0, regen: 0, hit_effect: self.hit_effect,
+            on_death: DeathCallback::Monster,
+        });
+        monster.ai = Some(self.ai.clone());
+        monster.alive = true;
+        monster
+    }
+}
+
+impl DataEntry for MonsterDef {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.name.is_empty() {
+            problems.push("the name is empty".to_string());
+        }
+        if self.hp <= 0 {
+            problems.push(format!("hp must be positive, got {}", self.hp));
+        }
+        if self.speed <= 0 {
+            problems.push(format!("speed must be positive, got {}", self.speed));
+        }
+        if let Some(effect) = self.hit_effect {
+            if effect.turns <= 0 {
+                problems.push(format!("the hit effect must last at least a turn, got {}", effect.turns));
+            }
+        }
+        problems.extend(spawn_weights_problems(&self.spawn_weights));
+        problems
+    }
+}
+
+/// Bonuses of an item that can be equipped.
+#[derive(Clone, Copy, Debug, RustcDecodable)]
+pub struct EquipmentDef {
+    pub slot: Slot,
+    pub max_hp_bonus: i32,
+    pub power_bonus: i32,
+    pub defense_bonus: i32,
+    pub speed_bonus: i32,
+}
+
+/// A kind of item, as described in the items data file.
+#[derive(Clone, Debug, RustcDecodable)]
+pub struct ItemDef {
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    // what happens when the item is used
+    pub use_effect: Item,
+    pub equipment: Option<EquipmentDef>,
+    // chance to appear from each dungeon level on, compared to the other items
+    pub spawn_weights: Vec<Transition>,
+}
+
+impl ItemDef {
+    pub fn spawn(&self, x: i32, y: i32) -> Object {
+        let mut item = Object::new(x, y, self.glyph, &self.name, self.color, false);
+        item.item = Some(self.use_effect);
+        item.equipment = self.equipment.map(|e| Equipment {
+            slot: e.slot,
+            equipped: false,
+            max_hp_bonus: e.max_hp_bonus,
+            power_bonus: e.power_bonus,
+            defense_bonus: e.defense_bonus,
+            speed_bonus: e.speed_bonus,
+        });
+        item
+    }
+}
+
+impl DataEntry for ItemDef {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.name.is_empty() {
+            problems.push("the name is empty".to_string());
+        }
+        match (self.use_effect, self.equipment.is_some()) {
+            (Item::Equipment, false) => problems.push("equipment needs an \"equipment\" section".to_string()),
+            (Item::Equipment, true) => {},
+            (_, true) => problems.push(format!("has an \"equipment\" section but is used as {:?}", self.use_effect)),
+            (_, false) => {},
+        }
+        problems.extend(spawn_weights_problems(&self.spawn_weights));
+        problems
+    }
+}
+
+/// The keys doing a command, as written in the key bindings file.
+#[derive(Clone, Debug, RustcDecodable)]
+pub struct BindingDef {
+    pub command: Command,
+    pub keys: Vec<String>,
+}
+
+impl DataEntry for BindingDef {
+    fn name(&self) -> &str {
+        self.command.description()
+    }
+
+    fn problems(&self) -> Vec<String> {
+        self.keys.iter().filter_map(|key| BoundKey::parse(key).err()).collect()
+    }
+}
+
+fn default_bindings() -> Vec<BindingDef> {
+    json::decode(DEFAULT_KEYS).expect("the default key bindings can be read")
+}
+
+/// Puts checked bindings together, a key can only do one thing on its own
+/// and one after a number of turns, and every command needs a key.
+fn keymap(path: &str, bindings: &[BindingDef]) -> Result<Keymap, DataError> {
+    let mut problems = vec![];
+    let mut keys: Vec<(BoundKey, Command)> = vec![];
+    for binding in bindings {
+        for key in &binding.keys {
+            let key = BoundKey::parse(key).unwrap();
+            let same_key = |&&(bound_key, command): &&(BoundKey, Command)| {
+                bound_key == key && command.needs_count() == binding.command.needs_count()
+            };
+            match keys.iter().find(same_key) {
+                Some(&(_, command)) if command != binding.command => {
+                    problems.push(format!("{} is bound to both \"{}\" and \"{}\"",
+                                          key, command.description(), binding.command.description()));
+                },
+                Some(_) => {},
+                None => keys.push((key, binding.command)),
+            }
+        }
+    }
+    for command in COMMANDS.iter() {
+        if !keys.iter().any(|&(_, bound_command)| bound_command == *command) {
+            problems.push(format!("no key to {}", command.description()));
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(Keymap::new(keys))
+    } else {
+        Err(DataError { path: path.into(), problems: problems })
+    }
+}
+
+fn spawn_weights_problems(spawn_weights: &[Transition]) -> Vec<String> {
+    spawn_weights.iter()
+        .filter(|transition| transition.level == 0)
+        .map(|_| "spawn weight levels start at 1".to_string())
+        .collect()
+}
+
+/// A data file that could not be read, with every bad entry in it.
+#[derive(Debug)]
+pub struct DataError {
+    pub path: String,
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try! { write!(f, "{}:", self.path) };
+        for problem in &self.problems {
+            try! { write!(f, "\n{}", problem) };
+        }
+        Ok(())
+    }
+}
+
+impl Error for DataError {
+    fn description(&self) -> &str {
+        "invalid data file"
+    }
+}
+
+/// Everything the dungeon is filled with and the keys to play, loaded from
+/// the data files at startup, and the settings the game is played with.
+pub struct GameData {
+    pub monsters: Vec<MonsterDef>,
+    pub items: Vec<ItemDef>,
+    pub keys: Keymap,
+    pub config: Config,
+}
+
+impl GameData {
+    pub fn load(config: Config) -> Result<GameData, DataError> {
+        let mut monsters: Vec<MonsterDef> = try! { load_file(MONSTERS_FILE) };
+        try! { validate(MONSTERS_FILE, "monster", &monsters) };
+        let mut items: Vec<ItemDef> = try! { load_file(ITEMS_FILE) };
+        try! { validate(ITEMS_FILE, "item", &items) };
+        // without a key bindings file the game is played with the default keys
+        let bindings: Vec<BindingDef> = match try! { load_optional_file(KEYS_FILE) } {
+            Some(bindings) => bindings,
+            None => default_bindings(),
+        };
+        try! { validate(KEYS_FILE, "binding", &bindings) };
+        let keys = try! { keymap(KEYS_FILE, &bindings) };
+
+        // from_dungeon_level expects the levels in ascending order
+        for monster in &mut monsters {
+            monster.spawn_weights.sort_by_key(|transition| transition.level);
+        }
+        for item in &mut items {
+            item.spawn_weights.sort_by_key(|transition| transition.level);
+        }
+
+        Ok(GameData { monsters: monsters, items: items, keys: keys, config: config })
+    }
+}
+
+fn load_file<T: Decodable>(path: &str) -> Result<T, DataError> {
+    let error = |problem: String| DataError { path: path.into(), problems: vec![problem] };
+
+    let mut contents = String::new();
+    let mut file = try! { File::open(path).map_err(|e| error(e.to_string())) };
+    try! { file.read_to_string(&mut contents).map_err(|e| error(e.to_string())) };
+    json::decode::<T>(&contents).map_err(|e| error(e.to_string()))
+}
+
+/// Like `load_file`, but None if there is no such file.
+fn load_optional_file<T: Decodable>(path: &str) -> Result<Option<T>, DataError> {
+    match fs::metadata(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        _ => load_file(path).map(Some),
+    }
+}
+
+/// Checks every entry, reporting each bad one by its position and name.
+fn validate<T: DataEntry>(path: &str, kind: &str, entries: &[T]) -> Result<(), DataError> {
+    let mut all_problems = vec![];
+    for (index, entry) in entries.iter().enumerate() {
+        for problem in entry.problems() {
+            all_problems.push(format!("{} #{} \"{}\": {}", kind, index + 1, entry.name(), problem));
+        }
+    }
+
+    if all_problems.is_empty() {
+        Ok(())
+    } else {
+        Err(DataError { path: path.into(), problems: all_problems })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_keys_are_valid() {
+        let bindings = default_bindings();
+        validate(KEYS_FILE, "binding", &bindings).unwrap();
+        keymap(KEYS_FILE, &bindings).unwrap();
+    }
+}
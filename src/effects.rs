@@ -0,0 +1,133 @@
+use tcod::colors::{self, Color};
+
+use game::*;
+use object::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub enum EffectKind {
+    Poison,
+    Regeneration,
+    Haste,
+    Slow,
+    Blindness,
+    Confusion,
+    Paralysis,
+}
+
+/// A temporary condition of a fighter, lasting a number of turns.
+#[derive(Clone, Copy, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct StatusEffect {
+    pub kind: EffectKind,
+    pub turns: i32,
+    // damage or healing per turn, unused by the other kinds
+    pub power: i32,
+}
+
+/// What happens when an object gets an effect it already has.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stacking {
+    // keep the longest duration and the strongest power
+    Refresh,
+    // the durations add up
+    Extend,
+    // the powers add up, the longest duration is kept
+    Intensify,
+}
+
+impl EffectKind {
+    pub fn stacking(self) -> Stacking {
+        match self {
+            EffectKind::Poison => Stacking::Intensify,
+            EffectKind::Regeneration => Stacking::Refresh,
+            EffectKind::Haste => Stacking::Refresh,
+            EffectKind::Slow => Stacking::Refresh,
+            EffectKind::Blindness => Stacking::Extend,
+            EffectKind::Confusion => Stacking::Extend,
+            EffectKind::Paralysis => Stacking::Refresh,
+        }
+    }
+
+    /// The effect that is cancelled by this one, if any.
+    pub fn opposite(self) -> Option<EffectKind> {
+        match self {
+            EffectKind::Haste => Some(EffectKind::Slow),
+            EffectKind::Slow => Some(EffectKind::Haste),
+            _ => None,
+        }
+    }
+
+    /// Used in messages: "the orc is no longer <adjective>".
+    pub fn adjective(self) -> &'static str {
+        match self {
+            EffectKind::Poison => "poisoned",
+            EffectKind::Regeneration => "regenerating",
+            EffectKind::Haste => "hasted",
+            EffectKind::Slow => "slowed",
+            EffectKind::Blindness => "blind",
+            EffectKind::Confusion => "confused",
+            EffectKind::Paralysis => "paralysed",
+        }
+    }
+
+    /// Two letters shown in the side panel.
+    pub fn icon(self) -> &'static str {
+        match self {
+            EffectKind::Poison => "Po",
+            EffectKind::Regeneration => "Rg",
+            EffectKind::Haste => "Ha",
+            EffectKind::Slow => "Sl",
+            EffectKind::Blindness => "Bl",
+            EffectKind::Confusion => "Co",
+            EffectKind::Paralysis => "Pa",
+        }
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            EffectKind::Poison => colors::GREEN,
+            EffectKind::Regeneration => colors::LIGHT_VIOLET,
+            EffectKind::Haste => colors::LIGHT_YELLOW,
+            EffectKind::Slow => colors::LIGHT_BLUE,
+            EffectKind::Blindness => colors::GREY,
+            EffectKind::Confusion => colors::LIGHT_CYAN,
+            EffectKind::Paralysis => colors::LIGHT_RED,
+        }
+    }
+}
+
+impl StatusEffect {
+    pub fn new(kind: EffectKind, turns: i32, power: i32) -> Self {
+        StatusEffect { kind: kind, turns: turns, power: power }
+    }
+
+    /// Combines this effect with a new one of the same kind.
+    pub fn stack(&mut self, other: StatusEffect) {
+        match self.kind.stacking() {
+            Stacking::Refresh => {
+                self.turns = ::std::cmp::max(self.turns, other.turns);
+                self.power = ::std::cmp::max(self.power, other.power);
+            },
+            Stacking::Extend => {
+                self.turns += other.turns;
+            },
+            Stacking::Intensify => {
+                self.turns = ::std::cmp::max(self.turns, other.turns);
+                self.power += other.power;
+            },
+        }
+    }
+
+    /// Called every turn the effect lasts, returns the experience given
+    /// if the object died from it.
+    pub fn on_tick(&self, object: &mut Object, game: &mut Game) -> Option<i32> {
+        match self.kind {
+            EffectKind::Poison => object.take_damage(self.power, game),
+            EffectKind::Regeneration => {
+                object.heal(self.power, game);
+                None
+            },
+            // the others change how the object moves and acts instead
+            _ => None,
+        }
+    }
+}
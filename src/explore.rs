@@ -0,0 +1,263 @@
+use tcod::colors;
+
+use config::*;
+use effects::EffectKind;
+use messages::MessageLog;
+use map::map_size;
+use pathfinding;
+use world::World;
+use frontend::visible_monsters;
+
+/// A tile the player knows it can walk on: explored floor without anything
+/// in sight standing on it.
+fn known_floor(world: &World, x: i32, y: i32) -> bool {
+    let tile = &world.game.map[x as usize][y as usize];
+    if !tile.explored || tile.blocked {
+        return false;
+    }
+    let in_fov = world.fov.is_in_fov(x, y);
+    !world.object_manager.objects.iter().enumerate().any(|(id, cell)| {
+        let object = cell.borrow();
+        id != PLAYER && object.blocks && in_fov && object.pos() == (x, y)
+    })
+}
+
+/// Known floor next to floor never seen, going there shows something new.
+/// Walls never seen don't count, they may never be seen when walls are not lit.
+fn is_frontier(world: &World, x: i32, y: i32) -> bool {
+    let (width, height) = map_size(&world.game.map);
+    if !known_floor(world, x, y) {
+        return false;
+    }
+    for nx in (x - 1)..(x + 2) {
+        for ny in (y - 1)..(y + 2) {
+            if nx < 0 || ny < 0 || nx >= width || ny >= height {
+                continue;
+            }
+            let tile = &world.game.map[nx as usize][ny as usize];
+            if !tile.explored && !tile.blocked {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// An item in sight that auto-explore goes to pick up, if it is set to.
+/// The ones the player dropped are left alone.
+fn wanted_item_at(world: &World, x: i32, y: i32) -> bool {
+    if !world.config.explore_pick_up || world.game.inventory.len() >= MAX_INVENTORY_SIZE as usize {
+        return false;
+    }
+    world.fov.is_in_fov(x, y) && world.object_manager.objects.iter().map(|c| c.borrow()).any(|object| {
+        object.pos() == (x, y) && object.item.is_some() && !object.dropped
+    })
+}
+
+/// True when the player stands on an item auto-explore picks up.
+pub fn should_pick_up(world: &World) -> bool {
+    let (x, y) = world.object_manager.objects[PLAYER].borrow().pos();
+    wanted_item_at(world, x, y)
+}
+
+/// The direction of the first step towards the closest tile left to explore
+/// or item to pick up, going only where the player has been or seen.
+/// None when there is nothing left to explore that can be reached.
+pub fn next_step(world: &World) -> Option<(i32, i32)> {
+    let from = world.object_manager.objects[PLAYER].borrow().pos();
+    let path = pathfinding::path_to_closest(&world.game.map, from,
+                                            |x, y| known_floor(world, x, y),
+                                            |x, y| (x, y) != from && (is_frontier(world, x, y) || wanted_item_at(world, x, y)));
+    path.and_then(|path| path.first().cloned()).map(|(x, y)| (x - from.0, y - from.1))
+}
+
+/// The direction of the first step on the shortest known way to a tile.
+/// None when the player is there or doesn't know a way.
+pub fn step_towards(world: &World, to: (i32, i32)) -> Option<(i32, i32)> {
+    let from = world.object_manager.objects[PLAYER].borrow().pos();
+    let path = pathfinding::path_to_closest(&world.game.map, from,
+                                            |x, y| known_floor(world, x, y),
+                                            |x, y| (x, y) == to);
+    path.and_then(|path| path.first().cloned()).map(|(x, y)| (x - from.0, y - from.1))
+}
+
+/// Where the stairs down are, if the player has seen them.
+pub fn known_stairs(world: &World) -> Option<(i32, i32)> {
+    world.object_manager.objects.iter().map(|c| c.borrow())
+        .find(|object| object.name == "stairs" && world.game.map[object.x as usize][object.y as usize].explored)
+        .map(|object| object.pos())
+}
+
+/// True when the player has all its hit points.
+pub fn healed(world: &World) -> bool {
+    let player = world.object_manager.objects[PLAYER].borrow();
+    player.fighter.map_or(true, |f| f.hp >= player.max_hp(&world.game))
+}
+
+/// Keeps an eye on what should stop the player from doing the same thing
+/// again and again: a monster coming into view, losing hit points, a status effect
+/// starting or ending, or an item that wasn't in sight before.
+pub struct Watch {
+    hp: i32,
+    effects: Vec<EffectKind>,
+    // the monsters in sight at the last check
+    monsters_seen: Vec<usize>,
+    // position and name of the items seen so far
+    items_seen: Vec<((i32, i32), String)>,
+}
+
+impl Watch {
+    pub fn new(world: &World) -> Self {
+        let mut watch = Watch {
+            hp: player_hp(world),
+            effects: player_effects(world),
+            monsters_seen: visible_monsters(world),
+            items_seen: vec![],
+        };
+        watch.items_seen = watch.new_items(world);
+        watch
+    }
+
+    /// True if the player should stop now, the reason is told in the log.
+    pub fn disturbed(&mut self, world: &mut World) -> bool {
+        // the monsters already in sight were there when the player chose to go on
+        let monsters = visible_monsters(world);
+        let new_monster = monsters.iter()
+            .find(|id| !self.monsters_seen.contains(id))
+            .map(|&id| world.object_manager.objects[id].borrow().name.clone());
+        self.monsters_seen = monsters;
+        if let Some(name) = new_monster {
+            world.game.log.add(format!("You see {}.", with_article(&name)), colors::YELLOW);
+            return true;
+        }
+
+        let hp = player_hp(world);
+        let hurt = hp < self.hp;
+        self.hp = hp;
+        if hurt {
+            world.game.log.add("You are hurt.", colors::YELLOW);
+            return true;
+        }
+
+        // the effects tell themselves when they start and end
+        let effects = player_effects(world);
+        let status_changed = effects != self.effects;
+        self.effects = effects;
+        if status_changed {
+            return true;
+        }
+
+        let new_items = self.new_items(world);
+        let first_name = new_items.first().map(|&(_, ref name)| name.clone());
+        self.items_seen.extend(new_items);
+        match first_name {
+            Some(name) => {
+                world.game.log.add(format!("You see {}.", with_article(&name)), colors::YELLOW);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// The items in sight not seen yet, except the ones the player dropped.
+    fn new_items(&self, world: &World) -> Vec<((i32, i32), String)> {
+        world.object_manager.objects.iter().map(|c| c.borrow())
+            .filter(|object| object.item.is_some() && !object.dropped && world.fov.is_in_fov(object.x, object.y))
+            .map(|object| (object.pos(), object.name.clone()))
+            .filter(|item| !self.items_seen.contains(item))
+            .collect()
+    }
+}
+
+fn player_hp(world: &World) -> i32 {
+    world.object_manager.objects[PLAYER].borrow().fighter.map_or(0, |f| f.hp)
+}
+
+fn player_effects(world: &World) -> Vec<EffectKind> {
+    world.object_manager.objects[PLAYER].borrow().effects.iter().map(|e| e.kind).collect()
+}
+
+/// "a" or "an" followed by the name.
+fn with_article(name: &str) -> String {
+    match name.chars().next() {
+        Some(first) if "aeiouAEIOU".contains(first) => format!("an {}", name),
+        _ => format!("a {}", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tcod::colors;
+    use game::{Game, GameMode};
+    use map::new_map;
+    use messages::Messages;
+    use object::Object;
+    use tile::Tile;
+    use super::*;
+
+    /// A corridor along y = 10 from x = 5 to 30, the player at `player_x`
+    /// knowing the corridor from `explored_from` to `explored_to`.
+    fn corridor(player_x: i32, explored_from: usize, explored_to: usize) -> World {
+        let config = Config::default();
+        let mut map = new_map(&config);
+        for column in &mut map[5..31] {
+            column[10] = Tile::empty();
+        }
+        let mut player = Object::new(player_x, 10, '@', "player", colors::WHITE, true);
+        player.alive = true;
+        let game = Game {
+            map: map,
+            log: Messages::new(),
+            inventory: vec![],
+            dungeon_level: 1,
+            levels: vec![],
+            seed: 0,
+            turn: 0,
+            mode: GameMode::Explore,
+            actions_taken: 0,
+        };
+        let mut world = World::from_saved(vec![player], game, config);
+        // whatever was in sight, only the given part is known
+        for column in world.game.map.iter_mut() {
+            for tile in column.iter_mut() {
+                tile.explored = false;
+            }
+        }
+        for x in explored_from..(explored_to + 1) {
+            world.game.map[x][10].explored = true;
+        }
+        world
+    }
+
+    #[test]
+    fn goes_to_the_closest_frontier() {
+        let world = corridor(12, 8, 20);
+        assert!(is_frontier(&world, 8, 10));
+        assert!(is_frontier(&world, 20, 10));
+        assert_eq!(next_step(&world), Some((-1, 0)));
+
+        let world = corridor(18, 8, 20);
+        assert_eq!(next_step(&world), Some((1, 0)));
+    }
+
+    #[test]
+    fn unseen_walls_are_no_frontier() {
+        let world = corridor(12, 8, 20);
+        // the walls of the corridor were never seen either
+        assert!(!is_frontier(&world, 12, 10));
+        assert!(!is_frontier(&world, 13, 10));
+    }
+
+    #[test]
+    fn nothing_left_to_explore() {
+        let world = corridor(12, 5, 30);
+        assert_eq!(next_step(&world), None);
+    }
+
+    #[test]
+    fn articles_follow_the_name() {
+        assert_eq!(with_article("orc"), "an orc");
+        assert_eq!(with_article("troll"), "a troll");
+        assert_eq!(with_article("Elf"), "an Elf");
+    }
+}
@@ -0,0 +1,501 @@
+use std::cmp;
+
+use tcod::colors::{self, Color};
+use tcod::input::Key;
+
+use config::*;
+use object::*;
+use game::*;
+use messages::Messages;
+use keys::{Keymap, COMMANDS};
+use world::World;
+use camera::Camera;
+use map::map_size;
+
+/// Something the game can be played on: it draws the world, shows menus
+/// and reads the keys. Keys and colors are tcod's, but only as plain data.
+pub trait Frontend {
+    /// True once the player closed the window.
+    fn is_closed(&self) -> bool;
+
+    /// Draws the world. `map_changed` tells the map has to be drawn again,
+    /// i.e. the player moved, its sight changed or it went to another level.
+    fn render(&mut self, world: &World, map_changed: bool);
+
+    /// The key pressed since the last call, a default key if there was none.
+    fn check_for_key(&mut self) -> Key;
+
+    /// True if a key was pressed, without waiting for one. It stops what
+    /// goes on over several turns, like exploring.
+    fn key_pressed(&mut self) -> bool;
+
+    /// The map tile left-clicked during the last `check_for_key`, if any.
+    fn take_click(&mut self) -> Option<(i32, i32)> {
+        None
+    }
+
+    fn clear(&mut self);
+
+    /// Prints a line of text centered on the screen.
+    fn print_center(&mut self, y: i32, text: &str, color: Color);
+
+    /// Shows a window with a header and lettered options, returns the chosen
+    /// option or None if any other key was pressed.
+    fn menu(&mut self, header: &str, options: &[&str], width: i32) -> Option<usize>;
+
+    fn msgbox(&mut self, text: &str, width: i32) {
+        self.menu(text, &[], width);
+    }
+
+    /// Like `msgbox`, without waiting for a key.
+    fn msg(&mut self, text: &str, width: i32);
+
+    /// Like `msg`, then waits for a key and returns it.
+    fn prompt(&mut self, text: &str, width: i32) -> Key;
+
+    /// Asks for a line of text, returns None if cancelled with Escape.
+    fn input_box(&mut self, header: &str, width: i32) -> Option<String>;
+
+    /// Shows a full screen of colored lines under a title, and waits for a key.
+    /// The lines must fit on the screen and are not wrapped.
+    fn show_page(&mut self, title: &str, lines: &[(String, Color)], footer: &str) -> Key;
+
+    /// Draws the world with a cursor on a map tile, the highlighted tiles,
+    /// a prompt at the top and a panel with the description, and waits for
+    /// what the player does.
+    fn show_cursor(&mut self, world: &World, cursor: (i32, i32), highlights: &[((i32, i32), Color)],
+                   prompt: &str, description: &[(String, Color)]) -> CursorInput;
+
+    /// The settings the front-end was made with, giving the screen layout.
+    fn config(&self) -> &Config;
+
+    fn toggle_fullscreen(&mut self) {}
+}
+
+/// What the player did while a cursor was shown on the map.
+#[derive(Clone, Copy, Debug)]
+pub enum CursorInput {
+    Key(Key),
+    // the mouse moved to a map tile
+    Hover(i32, i32),
+    // a map tile was clicked
+    Click(i32, i32),
+    Cancel,
+}
+
+/// A cursor moved over the map with the movement keys or the mouse, Tab
+/// jumping to the next of some monsters. It starts on the player and stays
+/// on the screen, which is centered on the player.
+struct MapCursor {
+    x: i32,
+    y: i32,
+    camera: Camera,
+    monsters: Vec<usize>,
+    next_monster: usize,
+}
+
+impl MapCursor {
+    fn new(world: &World, monsters: Vec<usize>, config: &Config) -> Self {
+        let (x, y) = world.object_manager.objects[PLAYER].borrow().pos();
+        let mut camera = Camera::new(config.camera_width(), config.camera_height());
+        camera.follow(x, y, &world.game.map);
+        MapCursor { x: x, y: y, camera: camera, monsters: monsters, next_monster: 0 }
+    }
+
+    fn pos(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+
+    fn to_next_monster(&mut self, world: &World) {
+        if !self.monsters.is_empty() {
+            let monster = world.object_manager.objects[self.monsters[self.next_monster % self.monsters.len()]].borrow();
+            self.x = monster.x;
+            self.y = monster.y;
+            self.next_monster += 1;
+        }
+    }
+
+    /// Moves the cursor for what the player did. Returns it if it was
+    /// something else, a click moves the cursor and is returned too.
+    fn handle(&mut self, input: CursorInput, world: &World, keys: &Keymap) -> Option<CursorInput> {
+        use tcod::input::KeyCode::Tab;
+
+        match input {
+            CursorInput::Key(key) => {
+                if let Some((dx, dy)) = keys.direction(key) {
+                    if self.camera.to_screen(self.x + dx, self.y + dy).is_some() {
+                        self.x += dx;
+                        self.y += dy;
+                    }
+                    None
+                } else if key.code == Tab {
+                    self.to_next_monster(world);
+                    None
+                } else {
+                    Some(input)
+                }
+            },
+            CursorInput::Hover(x, y) => {
+                self.x = x;
+                self.y = y;
+                None
+            },
+            CursorInput::Click(x, y) => {
+                self.x = x;
+                self.y = y;
+                Some(input)
+            },
+            CursorInput::Cancel => Some(input),
+        }
+    }
+}
+
+/// Lets the player pick a tile in sight, within range if there is one.
+/// The cursor starts on the closest enemy in range and Tab goes to the next
+/// one. The line from the player to the cursor is shown, in red out of
+/// range, and the area hit around the cursor if `radius` isn't 0.
+/// The prompt says what for. None if the player cancelled.
+pub fn target_tile(frontend: &mut Frontend, world: &World, keys: &Keymap, prompt: &str, max_range: Option<f32>,
+                   radius: i32) -> Option<(i32, i32)> {
+    use tcod::input::KeyCode::{Enter, Escape};
+
+    let player = world.object_manager.objects[PLAYER].borrow();
+    let in_range = |x: i32, y: i32| max_range.map_or(true, |range| player.distance(x, y) <= range);
+    let monsters = visible_monsters(world).into_iter().filter(|&id| {
+        let monster = world.object_manager.objects[id].borrow();
+        in_range(monster.x, monster.y)
+    }).collect();
+    let mut cursor = MapCursor::new(world, monsters, frontend.config());
+    cursor.to_next_monster(world);
+    let prompt = format!("{} Move the cursor or Tab to the next enemy, Enter to select, Esc to cancel.", prompt);
+
+    let (map_width, map_height) = map_size(&world.game.map);
+    loop {
+        let (x, y) = cursor.pos();
+        let mut highlights = vec![];
+        for area_x in (x - radius)..(x + radius + 1) {
+            for area_y in (y - radius)..(y + radius + 1) {
+                let (dx, dy) = (area_x - x, area_y - y);
+                let in_map = area_x >= 0 && area_y >= 0 && area_x < map_width && area_y < map_height;
+                if radius > 0 && in_map && ((dx * dx + dy * dy) as f32).sqrt() <= radius as f32 {
+                    highlights.push(((area_x, area_y), colors::DARKER_ORANGE));
+                }
+            }
+        }
+        for (line_x, line_y) in line(player.pos(), (x, y)) {
+            let color = if in_range(line_x, line_y) { colors::DARK_YELLOW } else { colors::DARK_RED };
+            highlights.push(((line_x, line_y), color));
+        }
+
+        let mut description = describe_tile(world, x, y);
+        if !world.fov.is_in_fov(x, y) {
+            description.push(("Out of sight.".into(), colors::RED));
+        } else if !in_range(x, y) {
+            description.push(("Out of range.".into(), colors::RED));
+        }
+
+        let input = frontend.show_cursor(world, (x, y), &highlights, &prompt, &description);
+        let (x, y) = cursor.pos();
+        let valid = world.fov.is_in_fov(x, y) && in_range(x, y);
+        match cursor.handle(input, world, keys) {
+            Some(CursorInput::Key(Key { code: Escape, .. })) | Some(CursorInput::Cancel) => return None,
+            Some(CursorInput::Key(Key { code: Enter, .. })) if valid => return Some((x, y)),
+            Some(CursorInput::Click(..)) => {
+                let (x, y) = cursor.pos();
+                if world.fov.is_in_fov(x, y) && in_range(x, y) {
+                    return Some((x, y));
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+/// The tiles on a straight line from a tile to another, without the first one.
+fn line(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+    let (mut x, mut y) = from;
+    let dx = (to.0 - x).abs();
+    let dy = -(to.1 - y).abs();
+    let step_x = if x < to.0 { 1 } else { -1 };
+    let step_y = if y < to.1 { 1 } else { -1 };
+    let mut error = dx + dy;
+    let mut tiles = vec![];
+    while (x, y) != to {
+        let double_error = 2 * error;
+        if double_error >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if double_error <= dx {
+            error += dx;
+            y += step_y;
+        }
+        tiles.push((x, y));
+    }
+    tiles
+}
+
+pub fn target_monster(frontend: &mut Frontend, world: &World, keys: &Keymap, prompt: &str,
+                      max_range: Option<f32>) -> Option<usize> {
+    loop {
+        match target_tile(frontend, world, keys, prompt, max_range, 0) {
+            Some((x, y)) => {
+                if let Some(id) = monster_at(x, y, max_range, &world.object_manager, &world.fov) {
+                    return Some(id)
+                }
+            },
+            None => return None,
+        }
+    }
+}
+
+/// Asks the player where to aim an item, None if it doesn't need aiming or
+/// the player cancelled.
+pub fn choose_target(item: Item, frontend: &mut Frontend, world: &World, keys: &Keymap) -> Option<(i32, i32)> {
+    match item.targeting(&world.config) {
+        Targeting::Nothing => None,
+        Targeting::Monster(range) => {
+            let prompt = "Choose an enemy to target.";
+            target_monster(frontend, world, keys, prompt, Some(range))
+                .map(|id| world.object_manager.objects[id].borrow().pos())
+        },
+        Targeting::Tile(range, radius) => {
+            let prompt = "Choose a target tile.";
+            target_tile(frontend, world, keys, prompt, range, radius)
+        },
+    }
+}
+
+pub fn inventory_menu(inventory: &[Object], header: &str, frontend: &mut Frontend) -> Option<usize> {
+    let options = if inventory.is_empty() {
+        vec!["Inventory is empty".into()]
+    } else {
+        inventory.iter().map(|item| {
+            match item.equipment {
+                Some(equipment) if equipment.equipped => {
+                    format!("{} (on {})", item.name, equipment.slot)
+                },
+                _ => item.name.clone(),
+            }
+        }).collect::<Vec<String>>()
+    };
+    let options: Vec<&str> = options.iter().map(|option| option.as_ref()).collect();
+
+    let inventory_index = frontend.menu(header, &options, INVENTORY_WIDTH);
+
+    if inventory.is_empty() {
+        None
+    } else {
+        inventory_index
+    }
+}
+
+/// Lists what every key does, as they are bound.
+pub fn show_help(frontend: &mut Frontend, keys: &Keymap) {
+    let mut help_text = String::new();
+    for command in COMMANDS.iter() {
+        let command_keys: Vec<String> = keys.keys(*command).iter().map(|key| key.to_string()).collect();
+        help_text.push_str(&format!("{}: {}\n", command.description(), command_keys.join(" ")));
+    }
+    help_text.push_str("\nPress any key to close this window.");
+    frontend.msgbox(&help_text, HELP_WIDTH);
+}
+
+/// Asks the player which stat to raise, until one is picked.
+pub fn level_up_menu(frontend: &mut Frontend, world: &World) -> LevelUpStat {
+    let player = world.object_manager.objects[PLAYER].borrow();
+    let fighter = player.fighter.as_ref().unwrap();
+    let options = [format!("Constitution (+20 HP, from {})", fighter.base_max_hp),
+                   format!("Strength (+1 attack, from {})", fighter.base_power),
+                   format!("Agility (+1 defense, from {})", fighter.base_defense)];
+    let options: Vec<&str> = options.iter().map(|option| option.as_ref()).collect();
+    let mut choice = None;
+
+    while choice.is_none() { // keep asking until choice is made
+        choice = frontend.menu("Level up! Choose a stat to raise:\n", &options, LEVEL_SCREEN_WIDTH);
+    };
+    match choice.unwrap() {
+        0 => LevelUpStat::Constitution,
+        1 => LevelUpStat::Strength,
+        2 => LevelUpStat::Agility,
+        _ => unreachable!(),
+    }
+}
+
+/// The monsters in sight, the closest first.
+pub fn visible_monsters(world: &World) -> Vec<usize> {
+    let player = world.object_manager.objects[PLAYER].borrow();
+    let mut monsters: Vec<usize> = world.object_manager.objects.iter().enumerate()
+        .filter(|&(id, cell)| {
+            let object = cell.borrow();
+            id != PLAYER && object.alive && object.fighter.is_some() && object.ai.is_some()
+                && world.fov.is_in_fov(object.x, object.y)
+        })
+        .map(|(id, _)| id)
+        .collect();
+    monsters.sort_by(|&id1, &id2| {
+        let distance1 = player.distance_to(&world.object_manager.objects[id1].borrow());
+        let distance2 = player.distance_to(&world.object_manager.objects[id2].borrow());
+        distance1.partial_cmp(&distance2).unwrap()
+    });
+    monsters
+}
+
+/// What the player sees or remembers on a tile: a line for the tile, then
+/// the objects on it with their state, wrapped to fit the look panel.
+pub fn describe_tile(world: &World, x: i32, y: i32) -> Vec<(String, Color)> {
+    let tile = &world.game.map[x as usize][y as usize];
+    if !tile.explored {
+        return vec![("You don't know what is there.".into(), colors::LIGHT_GREY)];
+    }
+    let in_fov = world.fov.is_in_fov(x, y);
+    let terrain = if tile.blocked { "a wall" } else { "the floor" };
+    let mut description = if in_fov {
+        vec![(format!("You see {}.", terrain), colors::WHITE)]
+    } else {
+        vec![(format!("You remember {}.", terrain), colors::LIGHT_GREY)]
+    };
+
+    // what is seen, or what can't be forgotten
+    let mut objects: Vec<_> = world.object_manager.objects.iter().map(|c| c.borrow())
+        .filter(|object| object.pos() == (x, y) && (in_fov || object.always_visible))
+        .collect();
+    // blocking objects first, like they are drawn on top
+    objects.sort_by(|o1, o2| o2.blocks.cmp(&o1.blocks));
+
+    for object in &objects {
+        let mut name = if object.name == "player" { "You".into() } else { object.name.clone() };
+        if object.alive {
+            if let Some(health) = object.health_state(&world.game) {
+                name = format!("{}, {}", name, health);
+            }
+            for effect in &object.effects {
+                name = format!("{}, {}", name, effect.kind.adjective());
+            }
+        }
+        description.push((name, object.color));
+
+        match (object.item, object.equipment) {
+            (_, Some(equipment)) => description.push((equipment.description(), colors::LIGHT_GREY)),
+            (Some(item), None) => description.push((item.description(&world.config), colors::LIGHT_GREY)),
+            (None, None) => {},
+        }
+    }
+
+    description.into_iter()
+        .flat_map(|(text, color)| wrap(&text, LOOK_WIDTH - 2).into_iter().map(move |line| (line, color)))
+        .collect()
+}
+
+/// Moves a cursor over the map with the movement keys, telling what is
+/// under it. Tab jumps from one monster in sight to the next.
+pub fn look(frontend: &mut Frontend, world: &World, keys: &Keymap) {
+    use tcod::input::KeyCode::Escape;
+
+    let mut cursor = MapCursor::new(world, visible_monsters(world), frontend.config());
+    let prompt = "Looking around. Move the cursor, Tab for the next monster, Esc to stop.";
+
+    loop {
+        let (x, y) = cursor.pos();
+        let description = describe_tile(world, x, y);
+        let input = frontend.show_cursor(world, (x, y), &[], prompt, &description);
+        match cursor.handle(input, world, keys) {
+            Some(CursorInput::Key(Key { code: Escape, .. })) | Some(CursorInput::Cancel) => return,
+            _ => {},
+        }
+    }
+}
+
+/// Shows the whole message log a page at a time, the latest messages first.
+/// '/' shows only the messages containing some text.
+pub fn message_history(frontend: &mut Frontend, log: &Messages) {
+    use tcod::input::KeyCode::*;
+
+    let (page_height, line_width) = {
+        let config = frontend.config();
+        ((config.screen_height - 4) as usize, config.screen_width - 10)
+    };
+    let footer = "Up/Down, PageUp/PageDown, Home/End to scroll, / to search, Esc to close";
+    let mut search: Option<String> = None;
+    let (mut lines, mut found) = history_lines(log, None, line_width);
+    // index of the first line on the page, the last page is shown first
+    let mut top = lines.len().saturating_sub(page_height);
+
+    loop {
+        let last_top = lines.len().saturating_sub(page_height);
+        let title = match search {
+            Some(ref text) => format!("Messages containing \"{}\": {} found", text, found),
+            None => "Message history".into(),
+        };
+        let bottom = cmp::min(top + page_height, lines.len());
+        let key = frontend.show_page(&title, &lines[top..bottom], footer);
+
+        match key.code {
+            Escape => return,
+            Up | NumPad8 => top = top.saturating_sub(1),
+            Down | NumPad2 => top = cmp::min(top + 1, last_top),
+            PageUp | NumPad9 => top = top.saturating_sub(page_height),
+            PageDown | NumPad3 => top = cmp::min(top + page_height, last_top),
+            Home | NumPad7 => top = 0,
+            End | NumPad1 => top = last_top,
+            _ if key.printable == '/' => {
+                if let Some(text) = frontend.input_box("Show the messages containing (empty for all):\n", 40) {
+                    let text = text.trim().to_lowercase();
+                    search = if text.is_empty() { None } else { Some(text) };
+                    let (new_lines, new_found) = history_lines(log, search.as_ref().map(|text| text.as_ref()), line_width);
+                    lines = new_lines;
+                    found = new_found;
+                    top = lines.len().saturating_sub(page_height);
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+/// The messages containing `search` as lines for `message_history`, no
+/// longer than `width`, each message with its turn in front of it, and how
+/// many messages there are.
+fn history_lines(log: &Messages, search: Option<&str>, width: i32) -> (Vec<(String, Color)>, usize) {
+    let mut lines = vec![];
+    let mut found = 0;
+    for message in log.iter() {
+        let text = message.display();
+        if let Some(search) = search {
+            if !text.to_lowercase().contains(search) {
+                continue;
+            }
+        }
+        found += 1;
+        for (index, line) in wrap(&text, width).into_iter().enumerate() {
+            if index == 0 {
+                lines.push((format!("{:>6}  {}", message.turn, line), message.color));
+            } else {
+                lines.push((format!("        {}", line), message.color));
+            }
+        }
+    }
+    (lines, found)
+}
+
+/// Splits text into lines no longer than `width`, breaking between words
+/// and at newlines.
+pub fn wrap(text: &str, width: i32) -> Vec<String> {
+    let width = width as usize;
+    let mut lines = vec![];
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        for word in paragraph.split(' ') {
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+                lines.push(line);
+                line = String::new();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        lines.push(line.chars().take(width).collect());
+    }
+    lines
+}
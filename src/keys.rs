@@ -0,0 +1,266 @@
+use std::fmt;
+
+use tcod::input::{Key, KeyCode};
+
+/// Something the player can do by pressing a key.
+#[derive(Clone, Copy, Debug, PartialEq, RustcDecodable)]
+pub enum Command {
+    North,
+    South,
+    West,
+    East,
+    NorthWest,
+    NorthEast,
+    SouthWest,
+    SouthEast,
+    Wait,
+    Rest,
+    Repeat,
+    Search,
+    Explore,
+    TravelStairs,
+    PickUp,
+    Drop,
+    Inventory,
+    Descend,
+    Ascend,
+    Character,
+    Look,
+    Messages,
+    Help,
+    Fullscreen,
+    Quit,
+}
+
+/// Every command, in the order they are listed in the help.
+pub const COMMANDS: [Command; 25] = [
+    Command::North, Command::South, Command::West, Command::East,
+    Command::NorthWest, Command::NorthEast, Command::SouthWest, Command::SouthEast,
+    Command::Wait, Command::Rest, Command::Repeat, Command::Search, Command::Explore, Command::TravelStairs,
+    Command::PickUp, Command::Drop, Command::Inventory,
+    Command::Descend, Command::Ascend, Command::Character, Command::Look,
+    Command::Messages, Command::Help, Command::Fullscreen, Command::Quit,
+];
+
+impl Command {
+    /// The direction of a movement command.
+    pub fn direction(self) -> Option<(i32, i32)> {
+        match self {
+            Command::North => Some((0, -1)),
+            Command::South => Some((0, 1)),
+            Command::West => Some((-1, 0)),
+            Command::East => Some((1, 0)),
+            Command::NorthWest => Some((-1, -1)),
+            Command::NorthEast => Some((1, -1)),
+            Command::SouthWest => Some((-1, 1)),
+            Command::SouthEast => Some((1, 1)),
+            _ => None,
+        }
+    }
+
+    /// True for the commands only typed after a number of turns, their keys
+    /// can do something else on their own (e.g. "s" for search and WASD).
+    pub fn needs_count(self) -> bool {
+        self == Command::Search
+    }
+
+    /// What it does, as shown in the help.
+    pub fn description(self) -> &'static str {
+        match self {
+            Command::North => "move up",
+            Command::South => "move down",
+            Command::West => "move left",
+            Command::East => "move right",
+            Command::NorthWest => "move up left",
+            Command::NorthEast => "move up right",
+            Command::SouthWest => "move down left",
+            Command::SouthEast => "move down right",
+            Command::Wait => "wait a turn",
+            Command::Rest => "rest until healed",
+            Command::Repeat => "type a number of turns to wait, rest or search",
+            Command::Search => "search, after a number of turns",
+            Command::Explore => "explore until something shows up",
+            Command::TravelStairs => "travel to the stairs down",
+            Command::PickUp => "pick up an item",
+            Command::Drop => "drop an item",
+            Command::Inventory => "use an item",
+            Command::Descend => "go down the stairs",
+            Command::Ascend => "go up the stairs",
+            Command::Character => "character information",
+            Command::Look => "look around",
+            Command::Messages => "message history",
+            Command::Help => "this help",
+            Command::Fullscreen => "toggle fullscreen",
+            Command::Quit => "save and go back to the main menu",
+        }
+    }
+}
+
+/// The key bindings used when there is no key bindings file: the
+/// `data/keys.json` shipped with the game, built in.
+pub const DEFAULT_KEYS: &'static str = include_str!("../data/keys.json");
+
+/// Names of the keys that don't print a character, as written in the
+/// key bindings file.
+const KEY_NAMES: [(&'static str, KeyCode); 37] = [
+    ("Escape", KeyCode::Escape), ("Backspace", KeyCode::Backspace), ("Tab", KeyCode::Tab),
+    ("Enter", KeyCode::Enter), ("Space", KeyCode::Spacebar),
+    ("Up", KeyCode::Up), ("Down", KeyCode::Down), ("Left", KeyCode::Left), ("Right", KeyCode::Right),
+    ("Home", KeyCode::Home), ("End", KeyCode::End), ("PageUp", KeyCode::PageUp), ("PageDown", KeyCode::PageDown),
+    ("Insert", KeyCode::Insert), ("Delete", KeyCode::Delete),
+    ("NumPad0", KeyCode::NumPad0), ("NumPad1", KeyCode::NumPad1), ("NumPad2", KeyCode::NumPad2),
+    ("NumPad3", KeyCode::NumPad3), ("NumPad4", KeyCode::NumPad4), ("NumPad5", KeyCode::NumPad5),
+    ("NumPad6", KeyCode::NumPad6), ("NumPad7", KeyCode::NumPad7), ("NumPad8", KeyCode::NumPad8),
+    ("NumPad9", KeyCode::NumPad9),
+    ("F1", KeyCode::F1), ("F2", KeyCode::F2), ("F3", KeyCode::F3), ("F4", KeyCode::F4),
+    ("F5", KeyCode::F5), ("F6", KeyCode::F6), ("F7", KeyCode::F7), ("F8", KeyCode::F8),
+    ("F9", KeyCode::F9), ("F10", KeyCode::F10), ("F11", KeyCode::F11), ("F12", KeyCode::F12),
+];
+
+/// A key as written in the key bindings file: the name of a key or a single
+/// character, after "Alt+" if Alt has to be held.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundKey {
+    code: KeyCode,
+    // the character typed, for KeyCode::Char
+    printable: char,
+    alt: bool,
+}
+
+impl BoundKey {
+    pub fn parse(text: &str) -> Result<BoundKey, String> {
+        let (alt, name) = if text.starts_with("Alt+") && text.len() > 4 {
+            (true, &text[4..])
+        } else {
+            (false, text)
+        };
+
+        if let Some(&(_, code)) = KEY_NAMES.iter().find(|&&(key_name, _)| key_name == name) {
+            return Ok(BoundKey { code: code, printable: '\0', alt: alt });
+        }
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(printable), None) if !printable.is_whitespace() => {
+                Ok(BoundKey { code: KeyCode::Char, printable: printable, alt: alt })
+            },
+            _ => Err(format!("unknown key \"{}\"", text)),
+        }
+    }
+
+    pub fn matches(&self, key: Key) -> bool {
+        if key.alt != self.alt {
+            return false;
+        }
+        match self.code {
+            KeyCode::Char => key.printable == self.printable,
+            code => key.code == code,
+        }
+    }
+}
+
+impl fmt::Display for BoundKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.alt {
+            try! { write!(f, "Alt+") };
+        }
+        match KEY_NAMES.iter().find(|&&(_, code)| code == self.code) {
+            Some(&(name, _)) => write!(f, "{}", name),
+            None => write!(f, "{}", self.printable),
+        }
+    }
+}
+
+/// Which key does what, loaded from the key bindings file.
+pub struct Keymap {
+    bindings: Vec<(BoundKey, Command)>,
+}
+
+impl Keymap {
+    pub fn new(bindings: Vec<(BoundKey, Command)>) -> Self {
+        Keymap { bindings: bindings }
+    }
+
+    /// The command bound to the key, if any.
+    pub fn command(&self, key: Key) -> Option<Command> {
+        self.bindings.iter()
+            .find(|&&(ref bound_key, command)| !command.needs_count() && bound_key.matches(key))
+            .map(|&(_, command)| command)
+    }
+
+    /// The command of a key typed after a number of turns, the commands
+    /// needing one come first.
+    pub fn counted_command(&self, key: Key) -> Option<Command> {
+        self.bindings.iter()
+            .find(|&&(ref bound_key, command)| command.needs_count() && bound_key.matches(key))
+            .map(|&(_, command)| command)
+            .or_else(|| self.command(key))
+    }
+
+    /// The direction of a movement key, if it is one.
+    pub fn direction(&self, key: Key) -> Option<(i32, i32)> {
+        self.command(key).and_then(|command| command.direction())
+    }
+
+    /// The keys bound to a command.
+    pub fn keys(&self, command: Command) -> Vec<BoundKey> {
+        self.bindings.iter()
+            .filter(|&&(_, bound_command)| bound_command == command)
+            .map(|&(bound_key, _)| bound_key)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, printable: char) -> Key {
+        Key { code: code, printable: printable, pressed: true, ..Default::default() }
+    }
+
+    #[test]
+    fn parses_key_names_and_characters() {
+        assert_eq!(BoundKey::parse("NumPad8"), Ok(BoundKey { code: KeyCode::NumPad8, printable: '\0', alt: false }));
+        assert_eq!(BoundKey::parse("k"), Ok(BoundKey { code: KeyCode::Char, printable: 'k', alt: false }));
+        assert_eq!(BoundKey::parse("Alt+Enter"), Ok(BoundKey { code: KeyCode::Enter, printable: '\0', alt: true }));
+        // a single "+" after "Alt+" is a character
+        assert_eq!(BoundKey::parse("Alt++"), Ok(BoundKey { code: KeyCode::Char, printable: '+', alt: true }));
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(BoundKey::parse("").is_err());
+        assert!(BoundKey::parse("Alt+").is_err());
+        assert!(BoundKey::parse("Foo").is_err());
+        assert!(BoundKey::parse(" ").is_err());
+        assert_eq!(BoundKey::parse("ctrl+x"), Err("unknown key \"ctrl+x\"".to_string()));
+    }
+
+    #[test]
+    fn matches_the_keys_pressed() {
+        let alt_enter = BoundKey::parse("Alt+Enter").unwrap();
+        assert!(alt_enter.matches(Key { alt: true, ..key(KeyCode::Enter, '\0') }));
+        assert!(!alt_enter.matches(key(KeyCode::Enter, '\0')));
+        let upper_d = BoundKey::parse("D").unwrap();
+        assert!(upper_d.matches(key(KeyCode::Char, 'D')));
+        assert!(!upper_d.matches(key(KeyCode::Char, 'd')));
+    }
+
+    #[test]
+    fn keys_after_a_count_prefer_the_counted_commands() {
+        let s = BoundKey::parse("s").unwrap();
+        let space = BoundKey::parse("Space").unwrap();
+        let keymap = Keymap::new(vec![(s, Command::South), (s, Command::Search), (space, Command::Wait)]);
+        assert_eq!(keymap.command(key(KeyCode::Char, 's')), Some(Command::South));
+        assert_eq!(keymap.counted_command(key(KeyCode::Char, 's')), Some(Command::Search));
+        assert_eq!(keymap.counted_command(key(KeyCode::Spacebar, ' ')), Some(Command::Wait));
+    }
+
+    #[test]
+    fn shows_keys_as_they_are_written() {
+        for text in &["Escape", "NumPad5", "Alt+Enter", "?", "Alt+x"] {
+            assert_eq!(BoundKey::parse(text).unwrap().to_string(), *text);
+        }
+    }
+
+}
@@ -2,281 +2,538 @@ extern crate tcod;
 extern crate rand;
 extern crate rustc_serialize;
 
-use std::cell::RefCell;
 use tcod::console::*;
 use tcod::colors::{self};
-use tcod::map::{Map as FovMap};
-use tcod::input::{self, Event, Key};
+use tcod::input::Key;
 
-use std::io::{Read, Write};
-use std::fs::File;
-use std::error::Error;
-use rustc_serialize::json;
+use std::env;
+use std::fs;
 
 mod config;
 mod tile;
 mod map;
 mod object;
 mod rect;
+mod bsp;
+mod cave;
+mod validator;
 mod messages;
 mod game;
+mod camera;
+mod pathfinding;
+mod effects;
+mod data;
+mod keys;
+mod world;
+mod frontend;
+mod explore;
+mod ui;
+mod terminal;
+mod replay;
+mod save;
 
 use config::*;
-use map::*;
-use object::*;
-use messages::*;
 use game::*;
-
-fn handle_keys(key: Key, tcod: &mut Tcod, game: &mut Game, object_manager: &mut ObjectsManager) -> PlayerAction 
+use messages::MessageLog;
+use camera::Camera;
+use data::GameData;
+use world::{World, Action};
+use frontend::*;
+use ui::Tcod;
+use terminal::Terminal;
+use replay::Replay;
+use save::*;
+
+fn handle_keys(key: Key, frontend: &mut Frontend, world: &mut World, data: &GameData) -> PlayerAction 
 {
-    use tcod::input::KeyCode::*;
+    use keys::Command::*;
     use game::PlayerAction::*;
 
-    let is_alive = object_manager.objects[PLAYER].borrow().alive;
+    let is_alive = world.object_manager.objects[PLAYER].borrow().alive;
+    let command = match data.keys.command(key) {
+        Some(command) => command,
+        None => {
+            // a number of turns typed before waiting or resting
+            return match count_digit(key, &data.keys, false) {
+                Some(digit) if digit > 0 && is_alive => repeat(frontend, world, data, Some(digit)),
+                _ => DidntTakeTurn,
+            };
+        },
+    };
 
-    match (key, is_alive) {
-        // Alt Enter fullscreen
-        (Key { code: Enter, alt: true, ..}, _) => {
-            let fullscreen = tcod.root.is_fullscreen();
-            tcod.root.set_fullscreen(!fullscreen);
+    match (command, is_alive) {
+        (Fullscreen, _) => {
+            frontend.toggle_fullscreen();
             DidntTakeTurn
         },
-        (Key { code: Escape, ..}, _) => Exit, // Exit game
+        (Quit, _) => Exit, // Exit game
         // Movement
-        (Key { code: Up, .. }, true) | (Key { code: NumPad8, .. }, true) => {
-            object_manager.player_move_or_attack(0, -1, game);
-            TookTurn 
-        },
-        (Key { code: Down, .. }, true) | (Key { code: NumPad2, .. }, true) => {
-            object_manager.player_move_or_attack(0, 1, game);
-            TookTurn 
-        },
-        (Key { code: Left, .. }, true) | (Key { code: NumPad4, .. }, true) => {
-            object_manager.player_move_or_attack(-1, 0, game);
-            TookTurn 
-        },
-        (Key { code: Right, .. }, true) | (Key { code: NumPad6, .. }, true) => {
-            object_manager.player_move_or_attack(1, 0, game);
-            TookTurn 
-        },
-        (Key { code: Home, .. }, true) | (Key { code: NumPad7, .. }, true) => {
-            object_manager.player_move_or_attack(-1, -1, game);
-            TookTurn 
-        },
-        (Key { code: PageUp, .. }, true) | (Key { code: NumPad9, .. }, true) => {
-            object_manager.player_move_or_attack(1, -1, game);
-            TookTurn 
+        (North, true) | (South, true) | (West, true) | (East, true) |
+        (NorthWest, true) | (NorthEast, true) | (SouthWest, true) | (SouthEast, true) => {
+            let (dx, dy) = command.direction().unwrap();
+            world.act(Action::Move(dx, dy), data)
         },
-        (Key { code: End, .. }, true) | (Key { code: NumPad1, .. }, true) => {
-            object_manager.player_move_or_attack(-1, 1, game);
-            TookTurn 
-        },
-        (Key { code: PageDown, .. }, true) | (Key { code: NumPad3, .. }, true) => {
-            object_manager.player_move_or_attack(1, 1, game);
-            TookTurn 
+        (Wait, true) => world.act(Action::Wait, data), // wait for turn
+        (Rest, true) => rest(frontend, world, data, None, true),
+        (Repeat, true) => repeat(frontend, world, data, None),
+        (Explore, true) => auto_explore(frontend, world, data),
+        (TravelStairs, true) => {
+            match explore::known_stairs(world) {
+                Some(stairs) => travel(frontend, world, data, stairs),
+                None => {
+                    world.game.log.add("You haven't found the stairs yet.", colors::LIGHT_GREY);
+                    DidntTakeTurn
+                },
+            }
         },
-        (Key { code: NumPad5, .. }, true) => { // wait for turn
-            TookTurn 
+        (Descend, true) => world.act(Action::Descend, data), // go down the stairs
+        (Ascend, true) => world.act(Action::Ascend, data), // go up the stairs
+        // Help screen
+        (Help, true) => {
+            show_help(frontend, &data.keys);
+            DidntTakeTurn
         },
-        (Key { printable: '<', .. }, true) | (Key { printable: ',', .. }, true) => { // go down the stairs
-            let player_pos = object_manager.objects[PLAYER].borrow().pos();
-            let player_on_stairs = object_manager.objects.iter().map(|c| c.borrow()).any(|object| {
-                object.pos() == player_pos && object.name == "stairs"
-            });
-            if player_on_stairs {
-                next_level(tcod, object_manager, game);
-            }
+        // Look around
+        (Look, _) => {
+            look(frontend, world, &data.keys);
             DidntTakeTurn
         },
-        // Help screen
-        (Key { printable: '?', .. }, true) | (Key { printable: '/', .. }, true) => { 
-            show_help(&mut tcod.root);
+        // Message history
+        (Messages, _) => {
+            message_history(frontend, &world.game.log);
             DidntTakeTurn
         },
         // Character
-        (Key { printable: 'c', .. }, true) => {
-            let player = object_manager.objects[PLAYER].borrow();
-            let level = player.level;
-            let level_up_xp = LEVEL_UP_BASE + level * LEVEL_UP_FACTOR;
+        (Character, true) => {
+            let player = world.object_manager.objects[PLAYER].borrow();
+            let game = &world.game;
             if let Some(fighter) = player.fighter.as_ref() {
                 let msg = format!("Character information\n\n\
                                     Level: {}\nExperience: {} / {}\n\n\
-                                    Maximum HP: {}\nAttack: {}\nDefense: {}", 
-                                    level, fighter.xp, level_up_xp, fighter.base_max_hp, player.power(game), fighter.base_defense);
-                msgbox(&msg, CHARACTER_SCREEN_WIDTH, &mut tcod.root);
+                                    Maximum HP: {}\nAttack: {}\nDefense: {}\nSpeed: {}\n\n\
+                                    Seed: {}\nMode: {:?}", 
+                                    player.level, fighter.xp, world.level_up_xp(), fighter.base_max_hp, player.power(game),
+                                    fighter.base_defense, player.speed(game), game.seed, game.mode);
+                frontend.msgbox(&msg, CHARACTER_SCREEN_WIDTH);
             }
             DidntTakeTurn
         },
         // Inventory
-        (Key { printable: 'g', .. }, true) => {
-            let player_pos = object_manager.objects[PLAYER].borrow().pos();
-            // pick up an item
-            let item_id = object_manager.objects.iter().map(|c| c.borrow()).position(|object| {
-                object.pos() == player_pos && object.item.is_some()
-            });
-            if let Some(item_id) = item_id {
-                pick_item_up(item_id, object_manager, game);
+        (PickUp, true) => world.act(Action::PickUp, data), // pick up an item
+        (Drop, true) => {
+            let inventory_index = inventory_menu(&world.game.inventory, "Press the key next to an item to DROP it, or any other to cancel.\n",
+                frontend);
+
+            match inventory_index {
+                Some(inventory_index) => world.act(Action::Drop(inventory_index), data),
+                None => DidntTakeTurn,
             }
-            DidntTakeTurn
         },
-        (Key {printable: 'd', .. }, true) => {
-            let inventory_index = inventory_menu(&game.inventory, "Press the key next to an item to DROP it, or any other to cancel.\n",
-                &mut tcod.root);
-
-            if let Some(inventory_index) = inventory_index {
-                drop_item(inventory_index, object_manager, game);
-            }
-            DidntTakeTurn
-        },
-        (Key {printable: 'i', .. }, true) => {
-            let inventory_index = inventory_menu(&game.inventory, "Press the key next to an item to USE it, or any other to cancel.\n",
-                &mut tcod.root);
-
-            if let Some(inventory_index) = inventory_index {
-                use_item(inventory_index, object_manager, game, tcod);
+        (Inventory, true) => {
+            let inventory_index = inventory_menu(&world.game.inventory, "Press the key next to an item to USE it, or any other to cancel.\n",
+                frontend);
+
+            match inventory_index {
+                Some(inventory_index) => {
+                    let target = match world.game.inventory[inventory_index].item {
+                        Some(item) => choose_target(item, frontend, world, &data.keys),
+                        None => None,
+                    };
+                    world.act(Action::UseItem(inventory_index, target), data)
+                },
+                None => DidntTakeTurn,
             }
-            DidntTakeTurn
         },
         _ => DidntTakeTurn,
     }
 }
 
-fn initialise_fov(map: &Map, tcod: &mut Tcod) {
-    for x in 0..MAP_WIDTH {
-        for y in 0..MAP_HEIGHT {
-            tcod.fov.set(x, y, !map[x as usize][y as usize].block_sight, !map[x as usize][y as usize].blocked)
+/// Walks to the closest place left to explore, step after step, until
+/// something shows up, there is nothing left to explore or a key is pressed.
+fn auto_explore(frontend: &mut Frontend, world: &mut World, data: &GameData) -> PlayerAction {
+    walk(frontend, world, data, |world| {
+        if explore::should_pick_up(world) {
+            return Some(Action::PickUp);
+        }
+        let step = explore::next_step(world);
+        if step.is_none() {
+            world.game.log.add("There is nothing left to explore.", colors::LIGHT_GREY);
         }
+        step.map(|(dx, dy)| Action::Move(dx, dy))
+    })
+}
+
+/// Walks to a tile the player knows the way to, the same way auto-explore does.
+fn travel(frontend: &mut Frontend, world: &mut World, data: &GameData, destination: (i32, i32)) -> PlayerAction {
+    if explore::step_towards(world, destination).is_none() {
+        if world.object_manager.objects[PLAYER].borrow().pos() != destination {
+            world.game.log.add("You don't know a way there.", colors::LIGHT_GREY);
+        }
+        return PlayerAction::DidntTakeTurn;
     }
-    tcod.con.clear();  // unexplored areas start black (which is the default background color)
+    walk(frontend, world, data, |world| {
+        explore::step_towards(world, destination).map(|(dx, dy)| Action::Move(dx, dy))
+    })
 }
 
-fn new_game(tcod: &mut Tcod) -> (ObjectsManager, Game) {
-    let mut player = Object::new(0, 0, '@', "player", colors::WHITE, true);
-    player.alive = true;
-    player.fighter = Some(Fighter{
-        base_max_hp: 100, hp: 100, base_defense: 1, base_power: 2, xp: 0,
-        on_death: DeathCallback::Player,
-    });
-    let level = 1;
-
-    let mut objects = vec![RefCell::new(player)];
-
-    let mut game = Game {
-        map: make_map(&mut objects, level),
-        log: vec![], // messages here
-        inventory: vec![],
-        dungeon_level: 1,
-    };
+/// Takes the actions `next_action` gives one after the other, until it gives
+/// none, something shows up or a key is pressed.
+fn walk<F>(frontend: &mut Frontend, world: &mut World, data: &GameData, mut next_action: F) -> PlayerAction
+    where F: FnMut(&mut World) -> Option<Action>
+{
+    let mut watch = explore::Watch::new(world);
+
+    let mut player_action = PlayerAction::DidntTakeTurn;
+    loop {
+        let action = match next_action(world) {
+            Some(action) => action,
+            None => break,
+        };
+
+        let previous_position = world.object_manager.objects[PLAYER].borrow().pos();
+        player_action = world.act(action, data);
+        // every action lets the monsters act, picking up too
+        if watch.disturbed(world) || player_action != PlayerAction::TookTurn {
+            break;
+        }
+        // something unseen is in the way
+        if let Action::Move(..) = action {
+            if world.object_manager.objects[PLAYER].borrow().pos() == previous_position {
+                break;
+            }
+        }
 
-    // initial equipment
-    let mut dagger = Object::new(0, 0, '-', "dagger", colors::SKY, false);
-    dagger.item = Some(Item::Sword);
-    dagger.equipment = Some(Equipment {
-        equipped: true,
-        slot: Slot::LeftHand,
-        max_hp_bonus: 0,
-        defense_bonus: 0,
-        power_bonus: 2
-    });
-    game.inventory.push(dagger);
+        frontend.render(world, true);
+        if frontend.is_closed() || frontend.key_pressed() {
+            break;
+        }
+    }
+    player_action
+}
 
-    let object_manager = ObjectsManager { objects: objects };
+/// Waits turn after turn, at most `max_turns` times and until healed if
+/// `until_healed`, stopping when something shows up or a key is pressed.
+fn rest(frontend: &mut Frontend, world: &mut World, data: &GameData, max_turns: Option<u32>,
+        until_healed: bool) -> PlayerAction {
+    if until_healed && explore::healed(world) {
+        world.game.log.add("You don't need to rest.", colors::LIGHT_GREY);
+        return PlayerAction::DidntTakeTurn;
+    }
+    let mut watch = explore::Watch::new(world);
+
+    let mut player_action = PlayerAction::DidntTakeTurn;
+    let mut turns = 0;
+    while max_turns.map_or(true, |max_turns| turns < max_turns) {
+        player_action = world.act(Action::Wait, data);
+        turns += 1;
+        if watch.disturbed(world) {
+            break;
+        }
+        if until_healed && explore::healed(world) {
+            world.game.log.add("You feel rested.", colors::LIGHT_GREY);
+            break;
+        }
 
-    initialise_fov(&game.map, tcod);
+        // resting is long, drawing every turn would make it longer
+        if turns % 10 == 0 {
+            frontend.render(world, false);
+        }
+        if frontend.is_closed() || frontend.key_pressed() {
+            break;
+        }
+    }
+    player_action
+}
 
-    // greeting
-    game.log.add("Welcome stranger! Prepare to die in these catacombs. Hahaha.", colors::RED);
+/// Reads a number of turns typed digit after digit, then waits, rests or
+/// searches that many times depending on the key pressed after it. Resting
+/// still stops once healed. The number starts with the repeat key, or with a digit
+/// that does nothing else: `first_digit`.
+fn repeat(frontend: &mut Frontend, world: &mut World, data: &GameData, first_digit: Option<u32>) -> PlayerAction {
+    // after the repeat key even the digits that move the player are part of
+    // the number, the terminal has no other digits
+    let any_digit = first_digit.is_none();
+    let mut turns = first_digit.unwrap_or(0);
+    let mut key;
+    loop {
+        let prompt = if turns == 0 {
+            "Type a number of turns, then the wait, rest or search key.".to_string()
+        } else {
+            format!("Repeat {} times: press the wait, rest or search key.", turns)
+        };
+        // closing the window gives no key, which ends the number
+        key = frontend.prompt(&prompt, 60);
+        match count_digit(key, &data.keys, any_digit) {
+            Some(digit) => turns = turns.saturating_mul(10).saturating_add(digit),
+            None => break,
+        }
+    }
 
-    (object_manager, game)
+    match data.keys.counted_command(key) {
+        Some(keys::Command::Quit) => PlayerAction::DidntTakeTurn,
+        _ if turns == 0 => {
+            world.game.log.add("That is not a number of times.", colors::LIGHT_GREY);
+            PlayerAction::DidntTakeTurn
+        },
+        Some(keys::Command::Wait) => rest(frontend, world, data, Some(turns), false),
+        Some(keys::Command::Rest) => rest(frontend, world, data, Some(turns), true),
+        // there is nothing hidden to find yet, searching is waiting
+        Some(keys::Command::Search) => rest(frontend, world, data, Some(turns), false),
+        _ => {
+            world.game.log.add("Only waiting, resting and searching can be repeated.", colors::LIGHT_GREY);
+            PlayerAction::DidntTakeTurn
+        },
+    }
 }
 
-fn play_game(object_manager: &mut ObjectsManager, game: &mut Game, tcod: &mut Tcod) {
-    let mut previous_player_position = (-1, -1);
-    let mut key = Default::default();
+/// The digit a key adds to a number of turns. Unless `any_digit`, only the
+/// digits bound to nothing count.
+fn count_digit(key: Key, keys: &keys::Keymap, any_digit: bool) -> Option<u32> {
+    if !any_digit && keys.command(key).is_some() {
+        return None;
+    }
+    key.printable.to_digit(10)
+}
 
-    while !tcod.root.window_closed() {
-        let (player_x, player_y) = object_manager.objects[PLAYER].borrow().pos();
-        let fov_recompute = previous_player_position != (player_x, player_y);
+/// Saves the game without asking, and tells in the log if it failed.
+fn autosave(world: &mut World, slot: usize) {
+    if let Err(_e) = save_game(world, slot) {
+        world.game.log.add("Could not save the game.", colors::RED);
+    }
+}
 
-        match input::check_for_event(input::MOUSE | input::KEY_PRESS) {
-            Some((_, Event::Mouse(m))) => tcod.mouse = m,
-            Some((_, Event::Key(k))) => key = k,
-            _ => key = Default::default(),
+fn play_game(world: &mut World, slot: usize, frontend: &mut Frontend, data: &GameData) {
+    let mut previous_player_position = (-1, -1);
+    let mut previous_player_blind = false;
+    let mut previous_dungeon_level = 0;
+    let mut last_autosave_turn = world.game.turn;
+    let mut death_handled = false;
+
+    while !frontend.is_closed() {
+        let (player_x, player_y) = world.object_manager.objects[PLAYER].borrow().pos();
+        let player_blind = world.object_manager.objects[PLAYER].borrow().has_effect(effects::EffectKind::Blindness);
+        let map_changed = previous_player_position != (player_x, player_y) || previous_player_blind != player_blind
+            || previous_dungeon_level != world.game.dungeon_level;
+
+        frontend.render(world, map_changed);
+
+        while world.level_up_pending() {
+            let stat = level_up_menu(frontend, world);
+            world.act(Action::LevelUp(stat), data);
         }
 
-        render_all(tcod, object_manager, game, fov_recompute);
-
-        tcod.root.flush();
-
-        level_up(object_manager, game, tcod);
-      
-        object_manager.draw_clear(&mut tcod.con);
-
         previous_player_position = (player_x, player_y);
+        previous_player_blind = player_blind;
+        previous_dungeon_level = world.game.dungeon_level;
+
+        // player's turn, the monsters act after it until it's the player's turn again
+        let key = frontend.check_for_key();
+        let was_alive = world.object_manager.objects[PLAYER].borrow().alive;
+        let player_action = match frontend.take_click() {
+            // clicking an explored tile walks there
+            Some((x, y)) if was_alive && world.game.map[x as usize][y as usize].explored => {
+                travel(frontend, world, data, (x, y))
+            },
+            _ => handle_keys(key, frontend, world, data),
+        };
+        let is_alive = world.object_manager.objects[PLAYER].borrow().alive;
+
+        if !is_alive && !death_handled {
+            death_handled = true;
+            // no coming back from death, unless exploring
+            if world.game.mode == GameMode::Roguelike && slot_used(slot) {
+                if let Err(_e) = delete_save(slot) {
+                    world.game.log.add("Could not delete the saved game.", colors::RED);
+                }
+            }
+        }
 
-        // player's turn
-        let player_action = handle_keys(key, tcod, game, object_manager);
         if player_action == PlayerAction::Exit {
-            msg("\nSaving game...\n", 24, &mut tcod.root);
-            match save_game(object_manager, game) {
+            if world.recorder.is_some() {
+                if let Err(_e) = fs::copy(replay_path(slot), last_replay_path()) {
+                    frontend.msgbox("\nError saving the replay.\n", 24);
+                }
+            }
+            if !is_alive {
+                // a dead game isn't saved, explore mode goes on from the last save
+                break
+            }
+            frontend.msg("\nSaving game...\n", 24);
+            match save_game(world, slot) {
                 Ok(_) => {
                     break
                 },
                 Err(_e) => {
-                    msgbox("\nError saving file.\n", 24, &mut tcod.root);
+                    frontend.msgbox("\nError saving file.\n", 24);
                 },
             }
             break
         }
 
-        // monsters turn
-        if object_manager.objects[PLAYER].borrow().alive && player_action == PlayerAction::TookTurn {
-            object_manager.ai_turn(game, &tcod.fov);
+        if is_alive && (world.game.dungeon_level != previous_dungeon_level
+                        || world.game.turn >= last_autosave_turn + data.config.autosave_turns) {
+            autosave(world, slot);
+            last_autosave_turn = world.game.turn;
+        }
+    }
+
+    // closing the window leaves the game as Escape does. Without saving, a
+    // roguelike game could go on from its last autosave. There is nothing left
+    // to show an error on
+    if frontend.is_closed() {
+        if world.recorder.is_some() {
+            let _ = fs::copy(replay_path(slot), last_replay_path());
         }
+        if world.object_manager.objects[PLAYER].borrow().alive {
+            let _ = save_game(world, slot);
+        }
+    }
+}
+
+/// Asks the player for a save slot, showing what is saved in each.
+fn choose_slot(header: &str, frontend: &mut Frontend) -> Option<usize> {
+    let options: Vec<String> = list_saves().iter().enumerate().map(|(slot, save)| {
+        match *save {
+            None => format!("Slot {}: empty", slot + 1),
+            Some(Ok(ref header)) => {
+                let saved = if header.timestamp == 0 { "unknown".into() } else { format_time(header.timestamp) };
+                format!("Slot {}: level {} character, dungeon level {}, saved {}",
+                        slot + 1, header.character_level, header.dungeon_level, saved)
+            },
+            Some(Err(_)) => format!("Slot {}: damaged save", slot + 1),
+        }
+    }).collect();
+    let options: Vec<&str> = options.iter().map(|option| option.as_ref()).collect();
+    frontend.menu(header, &options, SAVE_SLOTS_WIDTH)
+}
+
+/// Asks the player for a slot to start a new game in. A slot that is already
+/// used is only given once the player agreed to overwrite it.
+fn choose_new_game_slot(frontend: &mut Frontend) -> Option<usize> {
+    let slot = choose_slot("Choose a slot for the new game:\n", frontend);
+    frontend.clear();
+    match slot {
+        Some(slot) if slot_used(slot) => {
+            let confirm = frontend.menu("\nThere is a game saved in this slot. Overwrite it?\n", &["Yes", "No"], 24);
+            frontend.clear();
+            if confirm == Some(0) { Some(slot) } else { None }
+        },
+        slot => slot,
+    }
+}
+
+/// Asks the player what happens when the character dies.
+fn choose_game_mode(frontend: &mut Frontend) -> Option<GameMode> {
+    let options = &["Roguelike: one life, the saved game is deleted when you die",
+                    "Explore: the last save is kept, to go on from it after dying"];
+    let choice = frontend.menu("Choose the game mode:\n", options, SAVE_SLOTS_WIDTH);
+    frontend.clear();
+    match choice {
+        Some(0) => Some(GameMode::Roguelike),
+        Some(1) => Some(GameMode::Explore),
+        _ => None,
+    }
+}
+
+/// Starts a new game from the seed, in a slot and a mode the player picks.
+fn new_game(seed: u32, frontend: &mut Frontend, data: &GameData) {
+    let slot = match choose_new_game_slot(frontend) {
+        Some(slot) => slot,
+        None => return,
+    };
+    if let Some(mode) = choose_game_mode(frontend) {
+        let mut world = World::new(seed, mode, data);
+        // takes the slot at once, an old game in it is gone
+        autosave(&mut world, slot);
+        start_replay(&mut world, slot);
+        play_game(&mut world, slot, frontend, data);
+    }
+}
+
+/// Loads the game in a slot the player picks, or deletes it.
+fn continue_game(frontend: &mut Frontend, data: &GameData) {
+    let slot = match choose_slot("Choose a saved game:\n", frontend) {
+        Some(slot) => slot,
+        None => return,
+    };
+    frontend.clear();
+    if !slot_used(slot) {
+        frontend.msgbox("\nThis slot is empty.\n", 24);
+        frontend.clear();
+        return;
+    }
+
+    match frontend.menu("", &["Load", "Delete"], 24) {
+        Some(0) => {
+            frontend.clear();
+            match load_game(slot, data.config) {
+                Ok(mut world) => {
+                    // a roguelike game can't be loaded again from the same save after
+                    // things went wrong, it's back in its slot on the next autosave
+                    if world.game.mode == GameMode::Roguelike {
+                        if let Err(_e) = delete_save(slot) {
+                            world.game.log.add("Could not delete the saved game.", colors::RED);
+                        }
+                    }
+                    play_game(&mut world, slot, frontend, data)
+                },
+                Err(e) => frontend.msgbox(&format!("\n{}\n", e), 50),
+            }
+        },
+        Some(1) => {
+            frontend.clear();
+            let confirm = frontend.menu("\nDelete this saved game?\n", &["Yes", "No"], 24);
+            if confirm == Some(0) {
+                if let Err(_e) = delete_save(slot) {
+                    frontend.clear();
+                    frontend.msgbox("\nError deleting the saved game.\n", 24);
+                }
+            }
+        },
+        _ => {},
     }
+    frontend.clear();
 }
 
-fn main_menu(tcod: &mut Tcod) {
-    while !tcod.root.window_closed() {
-        let choices = &["Play a new game", "Continue last game", "Quit"];
+fn main_menu(frontend: &mut Frontend, seed: Option<u32>, data: &GameData) {
+    if let Err(e) = import_old_save() {
+        frontend.msgbox(&format!("\nCould not move the old saved game to the first slot.\n\n{}\n", e), 50);
+        frontend.clear();
+    }
+
+    while !frontend.is_closed() {
+        let choices = &["Play a new game", "Continue a saved game", "Play a new game from seed", "Quit"];
 
-        tcod.root.set_default_foreground(colors::LIGHT_YELLOW);
-        tcod.root.print_ex(SCREEN_WIDTH/2, SCREEN_HEIGHT/2 - 4,
-                   BackgroundFlag::None, TextAlignment::Center,
-                   "RUSTY ROGUELIKE");
-        tcod.root.print_ex(SCREEN_WIDTH/2, SCREEN_HEIGHT - 2,
-                   BackgroundFlag::None, TextAlignment::Center,
-                   "By name");
+        let screen_height = frontend.config().screen_height;
+        frontend.print_center(screen_height/2 - 4, "RUSTY ROGUELIKE", colors::LIGHT_YELLOW);
+        frontend.print_center(screen_height - 2, "By name", colors::LIGHT_YELLOW);
 
-        let choice = menu("", choices, 24, &mut tcod.root);
+        let choice = frontend.menu("", choices, 24);
 
         match choice {
             Some(0) => { // new game
-                let (mut object_manager, mut game) = new_game(tcod);
-                play_game(&mut object_manager, &mut game, tcod);
-                tcod.root.clear();
+                let seed = seed.unwrap_or_else(rand::random);
+                new_game(seed, frontend, data);
+                frontend.clear();
             },
             Some(1) => { // load game
-                match load_game() {
-                    Ok((objects, mut game)) => {
-                        let mut cells: Vec<RefCell<Object>> = vec![];
-                        for object in objects {
-                            cells.push(RefCell::new(object));
+                frontend.clear();
+                continue_game(frontend, data);
+            },
+            Some(2) => { // new game from a given seed
+                let text = frontend.input_box("Enter the seed of the dungeon:\n", 24);
+                frontend.clear();
+                if let Some(text) = text {
+                    match text.trim().parse::<u32>() {
+                        Ok(seed) => new_game(seed, frontend, data),
+                        Err(_e) => {
+                            frontend.msgbox("\nThe seed must be a whole number from 0 to 4294967295.\n", 24);
                         }
-
-                        let mut object_manager = ObjectsManager {objects: cells};
-                        initialise_fov(&game.map, tcod);
-                        play_game(&mut object_manager, &mut game, tcod);
-                        tcod.root.clear();
-                    },
-                    Err(_e) => {
-                        msgbox("\nNo saved game to load.\n", 24, &mut tcod.root);
-                        tcod.root.clear();
-                        continue;
                     }
+                    frontend.clear();
                 }
             },
-            Some(2) => { // quit
+            Some(3) => { // quit
                 break;
             },
             _ => {},
@@ -284,58 +541,122 @@ fn main_menu(tcod: &mut Tcod) {
     }
 }
 
-fn save_game(object_manager: &ObjectsManager, game: &Game) -> Result<(), Box<Error>> {
-    let save_data = try! { json::encode(&(&object_manager.objects, game)) };
-    let mut file = try! { File::create("savegame") };
-    try! { file.write_all(save_data.as_bytes()) };
-    Ok(())
+/// Reads the dungeon seed from `--seed <number>` on the command line.
+fn seed_from_args() -> Option<u32> {
+    arg_value("--seed").and_then(|seed| seed.parse().ok())
 }
 
-fn load_game() -> Result<(Vec<Object>, Game), Box<Error>> {
-    let mut json_save_state = String::new();
-    let mut file = try! { File::open("savegame") };
-    try! { file.read_to_string(&mut json_save_state) };
-    let result = try! { json::decode::<(Vec<Object>, Game)>(&json_save_state) };
-    Ok(result)
+/// The value following `name` on the command line.
+fn arg_value(name: &str) -> Option<String> {
+    let args: Vec<String> = env::args().collect();
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|index| args.get(index + 1))
+        .cloned()
 }
 
-fn next_level(tcod: &mut Tcod, object_manager: &mut ObjectsManager, game: &mut Game) {
-    game.log.add("You take a moment to rest, and recover your strength.", colors::VIOLET);
-    {  
-        let mut player = object_manager.objects[PLAYER].borrow_mut();
-        let heal_hp = player.max_hp(game) / 2;
-        player.heal(heal_hp, game);
-    }
+/// Plays a recorded game back, one action for each key press. Enter skips
+/// to the end and Escape stops watching. With `fast` it starts at the end.
+fn watch_replay(replay: &Replay, fast: bool, frontend: &mut Frontend, data: &GameData) {
+    use tcod::input::KeyCode::{Enter, Escape, NoKey};
+
+    let (mut world, mut next_action) = if fast {
+        (replay.run(data), replay.actions.len())
+    } else {
+        frontend.msgbox("\nWatching a replay. Press any key to play the next action, \
+                         Enter to skip to the end, Escape to stop.\n", 50);
+        (replay.start(data), 0)
+    };
 
-    game.log.add("After a rare moment of peace, you descend deeper into \
-                  the heart of the dungeon...", colors::RED);
+    while !frontend.is_closed() {
+        frontend.render(&world, true);
+
+        let key = frontend.check_for_key();
+        match key.code {
+            NoKey => {},
+            Escape => break,
+            Enter => {
+                while next_action < replay.actions.len() {
+                    world.act(replay.actions[next_action], data);
+                    next_action += 1;
+                }
+            },
+            _ => {
+                if next_action < replay.actions.len() {
+                    world.act(replay.actions[next_action], data);
+                    next_action += 1;
+                } else {
+                    frontend.msgbox("\nEnd of the replay.\n", 24);
+                    break;
+                }
+            },
+        }
+    }
+}
 
-    let mut objects = &mut object_manager.objects;
-    // check if player is the first(0) element, remove everything else
-    assert_eq!(&objects[PLAYER] as *const _, &objects[0] as *const _);
-    objects.truncate(1);
-    game.dungeon_level += 1;    
-    game.map = make_map(objects, game.dungeon_level);
+/// Loads the game data and shows the main menu on the given front-end,
+/// or plays back the replay file if there is one.
+fn run(frontend: &mut Frontend, seed: Option<u32>, replay_path: Option<String>, fast: bool) {
+    let config = *frontend.config();
+    let data = match GameData::load(config) {
+        Ok(data) => data,
+        Err(e) => {
+            frontend.msgbox(&format!("\nCould not load the game data.\n\n{}\n", e), 50);
+            return;
+        }
+    };
 
-    initialise_fov(&game.map, tcod);
+    match replay_path {
+        Some(path) => {
+            match Replay::load(&path).and_then(|replay| replay.check(&data).map(|_| replay)) {
+                Ok(replay) => watch_replay(&replay, fast, frontend, &data),
+                Err(e) => frontend.msgbox(&format!("\nCould not load the replay.\n\n{}\n", e), 50),
+            }
+        },
+        None => main_menu(frontend, seed, &data),
+    }
 }
 
 fn main() {
+    let seed = seed_from_args();
+    let replay_path = arg_value("--replay");
+    let fast = env::args().any(|arg| arg == "--fast");
+
+    // the screen size comes from the settings, they are read before any window is opened
+    let config = match Config::load(CONFIG_FILE) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("Could not load the settings.\n{}", e);
+            return;
+        }
+    };
+
+    // play in the terminal instead of a window
+    if env::args().any(|arg| arg == "--terminal") {
+        match Terminal::new(config) {
+            Ok(mut terminal) => run(&mut terminal, seed, replay_path, fast),
+            Err(e) => println!("Could not set up the terminal: {}", e),
+        }
+        return;
+    }
+
     let root = Root::initializer()
         .font("arial10x10.png", FontLayout::Tcod)
         .font_type(FontType::Greyscale)
-        .size(SCREEN_WIDTH, SCREEN_HEIGHT)
+        .size(config.screen_width, config.screen_height)
         .title("Rusty Roguelike")
         .init();
-    tcod::system::set_fps(LIMIT_FPS);
+    tcod::system::set_fps(config.limit_fps);
 
     let mut tcod = Tcod {
         root: root,
-        con: Offscreen::new(SCREEN_WIDTH, SCREEN_HEIGHT),
-        panel: Offscreen::new(SCREEN_WIDTH, PANEL_HEIGHT),
-        fov: FovMap::new(MAP_WIDTH, MAP_HEIGHT),
+        con: Offscreen::new(config.camera_width(), config.camera_height()),
+        panel: Offscreen::new(config.screen_width, config.panel_height),
         mouse: Default::default(),
+        click: None,
+        camera: Camera::new(config.camera_width(), config.camera_height()),
+        config: config,
     };
 
-   main_menu(&mut tcod);
+    run(&mut tcod, seed, replay_path, fast);
 }
@@ -2,25 +2,42 @@ extern crate rand;
 
 use std::cmp;
 use std::cell::RefCell;
+use std::collections::VecDeque;
 
-use rand::Rng;
+use rand::{Rng, StdRng};
 use tcod::colors::{self};
 
 use config::*;
 use tile::*;
 use rect::*;
 use object::*;
+use bsp::BspGenerator;
+use cave::CaveGenerator;
+use validator;
+use data::GameData;
 
 pub type Map = Vec<Vec<Tile>>;
 
-struct Transition {
-    level: u32,
-    value: u32,
+/// Width and height of a map. Levels keep the size they were made with,
+/// whatever the settings are now.
+pub fn map_size(map: &Map) -> (i32, i32) {
+    (map.len() as i32, map.first().map_or(0, |column| column.len() as i32))
+}
+
+/// A new map of the size in the settings, all walls.
+pub fn new_map(config: &Config) -> Map {
+    vec![vec![Tile::wall(); config.map_height as usize]; config.map_width as usize]
+}
+
+#[derive(Clone, Debug, RustcDecodable)]
+pub struct Transition {
+    pub level: u32,
+    pub value: u32,
 }
 
 /// Returns a value that depends on level. the table specifies what
 /// value occurs after each level, default is 0.
-fn from_dungeon_level(table: &[Transition], level: u32) -> u32 { // TODO auto sort by level ascending
+pub fn from_dungeon_level(table: &[Transition], level: u32) -> u32 { // TODO auto sort by level ascending
     table.iter()
         .rev()
         .find(|transition| level >= transition.level)
@@ -47,7 +64,31 @@ pub fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
     }    
 }
 
-pub fn place_objects(room: Rect, map: &Map, objects: &mut Vec<RefCell<Object>>, level: u32) {
+/// Joins two points with an L-shaped tunnel, bending at a random corner.
+pub fn create_l_tunnel((x1, y1): (i32, i32), (x2, y2): (i32, i32), map: &mut Map, rng: &mut StdRng) {
+    if rng.gen() {
+        create_h_tunnel(x1, x2, y1, map);
+        create_v_tunnel(y1, y2, x2, map);
+    } else {
+        create_v_tunnel(y1, y2, x1, map);
+        create_h_tunnel(x1, x2, y2, map);
+    }
+}
+
+pub fn place_objects(room: Rect, map: &Map, objects: &mut Vec<RefCell<Object>>, level: u32, data: &GameData, rng: &mut StdRng) {
+    let mut tiles = vec![];
+    for x in (room.x1 + 1)..room.x2 {
+        for y in (room.y1 + 1)..room.y2 {
+            tiles.push((x, y));
+        }
+    }
+    place_objects_on(&tiles, map, objects, level, data, rng);
+}
+
+/// Scatters monsters and items over the given tiles, as much as one room gets.
+pub fn place_objects_on(tiles: &[(i32, i32)], map: &Map, objects: &mut Vec<RefCell<Object>>, level: u32, data: &GameData,
+                        rng: &mut StdRng)
+{
     use rand::distributions::{Weighted, WeightedChoice, IndependentSample};
     // monsters
     // max monsters per room
@@ -57,48 +98,27 @@ pub fn place_objects(room: Rect, map: &Map, objects: &mut Vec<RefCell<Object>>,
         Transition {level: 6, value: 5},
     ], level);
 
-    let num_monsters = rand::thread_rng().gen_range(0, max_monsters + 1);
+    let num_monsters = rng.gen_range(0, max_monsters + 1);
 
-    // monster random table
-    let troll_chance = from_dungeon_level(&[
-        Transition {level: 3, value: 15},
-        Transition {level: 5, value: 30},
-        Transition {level: 7, value: 60},
-    ], level);
+    // monster random table, from the data file
+    let monster_chances = &mut data.monsters.iter().enumerate()
+        .map(|(index, monster)| Weighted {weight: from_dungeon_level(&monster.spawn_weights, level), item: index})
+        .filter(|chance| chance.weight > 0)
+        .collect::<Vec<_>>();
 
-    let monster_chances = &mut [
-        Weighted {weight: 80, item: MonsterType::Orc},
-        Weighted {weight: troll_chance, item: MonsterType::Troll},
-    ];
-    let monster_choice = WeightedChoice::new(monster_chances);
-
-    for _ in 0..num_monsters {
-        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
-
-        if !is_blocked(x, y, map, objects) {
-            let mut monster = match monster_choice.ind_sample(&mut rand::thread_rng()) {
-                MonsterType::Orc => { 
-                    let mut orc = Object::new(x, y, 'o', "orc", colors::DESATURATED_GREEN, true);
-                    orc.fighter = Some(Fighter{
-                        base_max_hp: 20, hp: 20, base_defense: 0, base_power: 4, xp: 35,
-                        on_death: DeathCallback::Monster,
-                    });
-                    orc.ai = Some(Ai::Basic);
-                    orc
-                },
-                MonsterType::Troll => {
-                    let mut troll = Object::new(x, y, 'T', "troll", colors::DARKER_GREEN, true);
-                    troll.fighter = Some(Fighter{
-                        base_max_hp: 30, hp: 30, base_defense: 2, base_power: 8, xp: 100,
-                        on_death: DeathCallback::Monster,
-                    });
-                    troll.ai = Some(Ai::Basic);
-                    troll
-                },
+    if !monster_chances.is_empty() {
+        let monster_choice = WeightedChoice::new(monster_chances);
+
+        for _ in 0..num_monsters {
+            let (x, y) = match rng.choose(tiles) {
+                Some(&tile) => tile,
+                None => return,
             };
-            monster.alive = true;
-            objects.push(RefCell::new(monster));
+
+            if !is_blocked(x, y, map, objects) {
+                let monster = data.monsters[monster_choice.ind_sample(rng)].spawn(x, y);
+                objects.push(RefCell::new(monster));
+            }
         }
     }
 
@@ -109,65 +129,28 @@ pub fn place_objects(room: Rect, map: &Map, objects: &mut Vec<RefCell<Object>>,
         Transition {level: 4, value: 2},
     ], level);
 
-    // item random table
-    let item_chances = &mut [
-        // healing potion always shows up, even if all other items have 0 chance
-        Weighted {weight: 35, item: Item::Heal},
-        Weighted {weight: from_dungeon_level(&[Transition{level: 4, value: 25}], level),
-                  item: Item::Lightning},
-        Weighted {weight: from_dungeon_level(&[Transition{level: 6, value: 25}], level),
-                  item: Item::Fireball},
-        Weighted {weight: from_dungeon_level(&[Transition{level: 2, value: 10}], level),
-                  item: Item::Confuse},
-        Weighted {weight: from_dungeon_level(&[Transition{level: 4, value: 5}], level),
-                  item: Item::Sword},
-        Weighted {weight: from_dungeon_level(&[Transition{level: 8, value: 15}], level),
-                  item: Item::Shield},
-    ];
+    // item random table, from the data file
+    let item_chances = &mut data.items.iter().enumerate()
+        .map(|(index, item)| Weighted {weight: from_dungeon_level(&item.spawn_weights, level), item: index})
+        .filter(|chance| chance.weight > 0)
+        .collect::<Vec<_>>();
 
-    let num_items = rand::thread_rng().gen_range(0, max_items + 1);
+    if item_chances.is_empty() {
+        return;
+    }
+    let item_choice = WeightedChoice::new(item_chances);
+
+    // never under the player's feet, whether it blocks or not
+    let player_pos = objects[PLAYER].borrow().pos();
+    let num_items = rng.gen_range(0, max_items + 1);
     for _ in 0..num_items {
-        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
-
-        if !is_blocked(x, y, map, objects) {
-            // item random table
-            let item_choice = WeightedChoice::new(item_chances);
-
-            let mut item = match item_choice.ind_sample(&mut rand::thread_rng()) {
-                Item::Heal => {
-                    let mut object = Object::new(x, y, '!', "healing potion", colors::VIOLET, false);
-                    object.item = Some(Item::Heal);
-                    object
-                },
-                Item::Lightning => {
-                    let mut object = Object::new(x, y, '#', "scroll of lightning bolt", colors::LIGHT_YELLOW, false);
-                    object.item = Some(Item::Lightning);
-                    object
-                },
-                Item::Fireball => {
-                    let mut object = Object::new(x, y, '#', "scroll of fireball", colors::LIGHT_YELLOW, false);
-                    object.item = Some(Item::Fireball);
-                    object
-                },
-                Item::Confuse => {
-                    let mut object = Object::new(x, y, '#', "scroll of confusion", colors::LIGHT_YELLOW, false);
-                    object.item = Some(Item::Confuse);
-                    object
-                },
-                Item::Sword => {
-                    let mut object = Object::new(x, y, '/', "sword", colors::SKY, false);
-                    object.item = Some(Item::Sword);
-                    object.equipment = Some(Equipment{equipped: false, slot: Slot::RightHand, power_bonus: 3, defense_bonus: 0, max_hp_bonus: 0});
-                    object
-                },
-                Item::Shield => {
-                    let mut object = Object::new(x, y, '[', "shield", colors::SKY, false);
-                    object.item = Some(Item::Sword);
-                    object.equipment = Some(Equipment{equipped: false, slot: Slot::LeftHand, power_bonus: 0, defense_bonus: 1, max_hp_bonus: 0});
-                    object
-                },
-            };
+        let (x, y) = match rng.choose(tiles) {
+            Some(&tile) => tile,
+            None => return,
+        };
+
+        if (x, y) != player_pos && !is_blocked(x, y, map, objects) {
+            let mut item = data.items[item_choice.ind_sample(rng)].spawn(x, y);
             item.always_visible = true;
             objects.push(RefCell::new(item));
         }
@@ -184,50 +167,170 @@ pub fn is_blocked(x: i32, y: i32, map: &Map, objects: &[RefCell<Object>]) -> boo
     })
 }
 
-pub fn make_map(objects: &mut Vec<RefCell<Object>>, level: u32) -> Map {
-    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
-    let mut rooms = vec![];
+pub fn place_stairs(x: i32, y: i32, objects: &mut Vec<RefCell<Object>>) {
+    let mut stairs = Object::new(x, y, '<', "stairs", colors::WHITE, false);
+    stairs.always_visible = true;
+    objects.push(RefCell::new(stairs));
+}
+
+/// Stairs leading to the level above, the player arrives on them.
+pub fn place_up_stairs(x: i32, y: i32, objects: &mut Vec<RefCell<Object>>) {
+    let mut stairs = Object::new(x, y, '>', "up stairs", colors::WHITE, false);
+    stairs.always_visible = true;
+    objects.push(RefCell::new(stairs));
+}
+
+/// Puts the player in the first room, the stairs in the last one
+/// and monsters and items everywhere.
+pub fn populate_rooms(rooms: &[Rect], map: &Map, objects: &mut Vec<RefCell<Object>>, level: u32, data: &GameData,
+                      rng: &mut StdRng)
+{
+    let (first_room_x, first_room_y) = rooms[0].center();
+    objects[PLAYER].borrow_mut().set_pos(first_room_x, first_room_y);
+
+    for room in rooms {
+        place_objects(*room, map, objects, level, data, rng);
+    }
+
+    let (last_room_x, last_room_y) = rooms[rooms.len() - 1].center();
+    place_stairs(last_room_x, last_room_y, objects);
+}
+
+/// Builds a dungeon level: carves the map, puts the player and the stairs
+/// on it and fills it with monsters and items.
+pub trait MapGenerator {
+    fn generate(&self, objects: &mut Vec<RefCell<Object>>, level: u32, data: &GameData, rng: &mut StdRng) -> Map;
+}
 
-    for _ in 0..MAX_ROOMS {
-        let w = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-        let h = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-        let x = rand::thread_rng().gen_range(0, MAP_WIDTH - w);
-        let y = rand::thread_rng().gen_range(0, MAP_HEIGHT - h);
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MapType {
+    Rooms,
+    Bsp,
+    Cave,
+}
 
-        let new_room = Rect::new(x, y, w, h);
-        // check for overlapping with existing ones
-        let failed = rooms.iter().any(|other_room| new_room.intersects_with(other_room));
+/// Random rooms thrown on the map, the ones overlapping others are skipped.
+pub struct RoomsGenerator;
 
-        if !failed {
-            create_room(new_room, &mut map);
-            let (new_x, new_y) = new_room.center();
+impl MapGenerator for RoomsGenerator {
+    fn generate(&self, objects: &mut Vec<RefCell<Object>>, level: u32, data: &GameData, rng: &mut StdRng) -> Map {
+        let config = &data.config;
+        let mut map = new_map(config);
+        let mut rooms = vec![];
 
-            if rooms.is_empty() { // first room
-                objects[PLAYER].borrow_mut().set_pos(new_x, new_y);
-            } else {
-                // connect the room to the previous room with a tunnel
-                let (prev_x, prev_y) = rooms[rooms.len() - 1].center();
+        for _ in 0..config.max_rooms {
+            let w = rng.gen_range(config.room_min_size, config.room_max_size + 1);
+            let h = rng.gen_range(config.room_min_size, config.room_max_size + 1);
+            let x = rng.gen_range(0, config.map_width - w);
+            let y = rng.gen_range(0, config.map_height - h);
 
-                // random order
-                if rand::random() {
-                    create_h_tunnel(prev_x, new_x, prev_y, &mut map);
-                    create_v_tunnel(prev_y, new_y, new_x, &mut map);
+            let new_room = Rect::new(x, y, w, h);
+            // check for overlapping with existing ones
+            let failed = rooms.iter().any(|other_room| new_room.intersects_with(other_room));
+
+            if !failed {
+                create_room(new_room, &mut map);
+                let (new_x, new_y) = new_room.center();
+
+                if rooms.is_empty() { // first room
+                    objects[PLAYER].borrow_mut().set_pos(new_x, new_y);
                 } else {
-                    create_v_tunnel(prev_y, new_y, prev_x, &mut map);
-                    create_h_tunnel(prev_x, new_x, new_y, &mut map);
+                    // connect the room to the previous room with a tunnel
+                    let prev_center = rooms[rooms.len() - 1].center();
+                    create_l_tunnel(prev_center, (new_x, new_y), &mut map, rng);
                 }
+
+                place_objects(new_room, &map, objects, level, data, rng);
+                rooms.push(new_room);
             }
+        }
+
+        // add stairs to the center of last room
+        let (last_room_x, last_room_y) = rooms[rooms.len() - 1].center();
+        place_stairs(last_room_x, last_room_y, objects);
+
+        map
+    }
+}
+
+/// Picks the map generator for a dungeon level.
+fn map_generator(level: u32, rng: &mut StdRng) -> Box<MapGenerator> {
+    use rand::distributions::{Weighted, WeightedChoice, IndependentSample};
+
+    let map_chances = &mut [
+        Weighted {weight: from_dungeon_level(&[
+            Transition {level: 1, value: 60},
+            Transition {level: 4, value: 30},
+        ], level), item: MapType::Rooms},
+        Weighted {weight: from_dungeon_level(&[
+            Transition {level: 1, value: 40},
+            Transition {level: 4, value: 70},
+        ], level), item: MapType::Bsp},
+        Weighted {weight: from_dungeon_level(&[
+            Transition {level: 3, value: 20},
+            Transition {level: 6, value: 35},
+        ], level), item: MapType::Cave},
+    ];
+    let map_choice = WeightedChoice::new(map_chances);
 
-            place_objects(new_room, &map, objects, level);
-            rooms.push(new_room);
+    match map_choice.ind_sample(rng) {
+        MapType::Rooms => Box::new(RoomsGenerator),
+        MapType::Bsp => Box::new(BspGenerator),
+        MapType::Cave => Box::new(CaveGenerator),
+    }
+}
+
+/// All the tiles reachable from `start` without crossing blocked ones,
+/// in breadth-first order so the farthest tiles come last.
+pub fn flood_fill(map: &Map, start: (i32, i32)) -> Vec<(i32, i32)> {
+    let (width, height) = map_size(map);
+    let mut visited = vec![vec![false; height as usize]; width as usize];
+    let mut reached = vec![];
+    let mut queue = VecDeque::new();
+
+    let (start_x, start_y) = start;
+    if map[start_x as usize][start_y as usize].blocked {
+        return reached;
+    }
+    visited[start_x as usize][start_y as usize] = true;
+    queue.push_back(start);
+
+    while let Some((x, y)) = queue.pop_front() {
+        reached.push((x, y));
+        for dx in -1..2 {
+            for dy in -1..2 {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= width || ny >= height {
+                    continue;
+                }
+                if !visited[nx as usize][ny as usize] && !map[nx as usize][ny as usize].blocked {
+                    visited[nx as usize][ny as usize] = true;
+                    queue.push_back((nx, ny));
+                }
+            }
         }
     }
+    reached
+}
 
-    // add stairs to the center of last room
-    let (last_room_x, last_room_y) = rooms[rooms.len() - 1].center();
-    let mut stairs = Object::new(last_room_x, last_room_y, '<', "stairs", colors::WHITE, false);
-    stairs.always_visible = true;
-    objects.push(RefCell::new(stairs));
+pub fn make_map(objects: &mut Vec<RefCell<Object>>, level: u32, data: &GameData, rng: &mut StdRng) -> Map {
+    let mut attempts = 1;
+    loop {
+        let generator = map_generator(level, rng);
+        let mut map = generator.generate(objects, level, data, rng);
 
-    map
+        if validator::is_valid(&map, objects) {
+            return map;
+        }
+        if attempts >= data.config.max_map_attempts {
+            // give up on luck and dig to whatever is sealed in
+            validator::repair(&mut map, objects, rng);
+            return map;
+        }
+
+        // throw away everything but the player and try again
+        assert_eq!(PLAYER, 0);
+        objects.truncate(1);
+        attempts += 1;
+    }
 }
@@ -1,13 +1,51 @@
+use std::slice;
+
 use config::*;
 use tcod::colors::{Color};
 
-pub type Messages = Vec<(String, Color)>;
+/// A line of the message log. The same message said several times in a row
+/// is kept once, with the number of times.
+#[derive(Clone, Debug, RustcEncodable, RustcDecodable)]
+pub struct Message {
+    pub text: String,
+    pub color: Color,
+    // turn it was last said on
+    pub turn: u32,
+    pub count: u32,
+}
+
+impl Message {
+    /// The text as shown, with the count of a repeated message.
+    pub fn display(&self) -> String {
+        if self.count > 1 {
+            format!("{} x{}", self.text, self.count)
+        } else {
+            self.text.clone()
+        }
+    }
+}
+
+/// The last `MSG_HISTORY` messages, oldest first.
+#[derive(RustcEncodable, RustcDecodable)]
+pub struct Messages {
+    messages: Vec<Message>,
+    // turn given to the messages added now
+    turn: u32,
+}
+
+impl Messages {
+    pub fn new() -> Self {
+        Messages { messages: vec![], turn: 0 }
+    }
+
+    /// Messages added from now on are said on this turn.
+    pub fn set_turn(&mut self, turn: u32) {
+        self.turn = turn;
+    }
 
-fn message<T: Into<String>>(messages: &mut Messages, message: T, color: Color) {
-    if messages.len() == MSG_HEIGHT {
-        messages.remove(0);
+    pub fn iter(&self) -> slice::Iter<Message> {
+        self.messages.iter()
     }
-    messages.push((message.into(), color));
 }
 
 pub trait MessageLog {
@@ -16,6 +54,54 @@ pub trait MessageLog {
 
 impl MessageLog for Messages {
     fn add<T: Into<String>>(&mut self, message: T, color: Color) {
-        self.push((message.into(), color));
+        let text = message.into();
+        if let Some(last) = self.messages.last_mut() {
+            if last.text == text && last.color == color {
+                last.count += 1;
+                last.turn = self.turn;
+                return;
+            }
+        }
+
+        if self.messages.len() == MSG_HISTORY {
+            self.messages.remove(0);
+        }
+        self.messages.push(Message { text: text, color: color, turn: self.turn, count: 1 });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tcod::colors;
+    use super::*;
+
+    fn texts(log: &Messages) -> Vec<String> {
+        log.iter().map(|message| message.display()).collect()
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn repeated_messages_are_collapsed() {
+        let mut log = Messages::new();
+        log.add("The orc attacks you.", colors::WHITE);
+        log.set_turn(3);
+        log.add("The orc attacks you.", colors::WHITE);
+        log.add("The orc attacks you.", colors::RED);
+        log.add("You hit the orc.", colors::WHITE);
+        log.add("The orc attacks you.", colors::WHITE);
+        assert_eq!(texts(&log), vec!["The orc attacks you. x2", "The orc attacks you.", "You hit the orc.",
+                                     "The orc attacks you."]);
+        // said again on a later turn
+        assert_eq!(log.iter().next().unwrap().turn, 3);
+    }
+
+    #[test]
+    fn only_the_last_messages_are_kept() {
+        let mut log = Messages::new();
+        for i in 0..MSG_HISTORY + 5 {
+            log.add(format!("message {}", i), colors::WHITE);
+        }
+        assert_eq!(log.iter().count(), MSG_HISTORY);
+        assert_eq!(log.iter().next().unwrap().text, "message 5");
+        assert_eq!(log.iter().last().unwrap().text, format!("message {}", MSG_HISTORY + 4));
+    }
+}
@@ -1,9 +1,9 @@
 extern crate rand;
 
-use tcod::console::*;
 use tcod::colors::{self, Color};
 use tcod::map::{Map as FovMap};
 use std::cell::RefCell;
+use std::cmp;
 use std::ops::DerefMut;
 use std::ops::Deref;
 use rand::Rng;
@@ -12,6 +12,8 @@ use config::*;
 use map::*;
 use messages::*;
 use game::*;
+use pathfinding;
+use effects::*;
 
 #[derive(Debug, RustcEncodable, RustcDecodable)]
 pub struct Object {
@@ -28,6 +30,9 @@ pub struct Object {
     pub always_visible: bool,
     pub level: i32,
     pub equipment: Option<Equipment>,
+    pub effects: Vec<StatusEffect>,
+    // an item the player dropped, auto-explore leaves it where it is
+    pub dropped: bool,
 }
 
 impl Object {
@@ -46,6 +51,8 @@ impl Object {
             always_visible: false,
             level: 1,
             equipment: None,
+            effects: vec![],
+            dropped: false,
         }
     }
 
@@ -68,15 +75,6 @@ impl Object {
         (((x - self.x).pow(2) + (y - self.y).pow(2)) as f32).sqrt()
     }
 
-    pub fn draw(&self, con: &mut Console) {
-        con.set_default_foreground(self.color);
-        con.put_char(self.x, self.y, self.char, BackgroundFlag::None);
-    }
-
-    pub fn clear(&self, con: &mut Console) {
-        con.put_char(self.x, self.y, ' ', BackgroundFlag::None);
-    }
-
     pub fn take_damage(&mut self, damage: i32, game: &mut Game) -> Option<i32> {
         if let Some(fighter) = self.fighter.as_mut() {
             if damage > 0 {
@@ -104,6 +102,13 @@ impl Object {
                 if let Some(f) = self.fighter.as_mut() {
                     f.xp += xp;
                 }
+            } else if let Some(effect) = self.fighter.and_then(|f| f.hit_effect) {
+                target.add_effect(effect);
+                if target.fighter.map_or(false, |f| f.on_death == DeathCallback::Player) {
+                    game.log.add(format!("You are {}!", effect.kind.adjective()), effect.kind.color());
+                } else {
+                    game.log.add(format!("The {} is {}!", target.name, effect.kind.adjective()), effect.kind.color());
+                }
             }
         } else {
             game.log.add(format!("{} attacks {} but it has no effect!", self.name, target.name),
@@ -121,7 +126,7 @@ impl Object {
         }
     }
 
-    pub fn equip(&mut self, log: &mut Vec<(String, Color)>) {
+    pub fn equip(&mut self, log: &mut Messages) {
         if self.item.is_none() {
             log.add(format!("Can't equip {:?} because it's not an Item.", self),
                 colors::RED);
@@ -139,7 +144,7 @@ impl Object {
         }
     }
 
-    pub fn dequip(&mut self, log: &mut Vec<(String, Color)>) {
+    pub fn dequip(&mut self, log: &mut Messages) {
         if self.item.is_none() {
             log.add(format!("Can't dequip {:?} because it's not an Item.", self),
                             colors::RED);
@@ -175,6 +180,58 @@ impl Object {
         base_max_hp + bonus
     }
 
+    /// Energy gained every tick, the more the more often the object acts.
+    pub fn speed(&self, game: &Game) -> i32 {
+        let base_speed = self.fighter.map_or(0, |f| f.base_speed);
+        let bonus = self.get_all_equipped(game).iter().fold(0, |sum, e| sum + e.speed_bonus);
+        let mut speed = base_speed + bonus;
+        if self.has_effect(EffectKind::Haste) {
+            speed = speed * 3 / 2;
+        }
+        if self.has_effect(EffectKind::Slow) {
+            speed /= 2;
+        }
+        // at least 1, or time would stop waiting for it to act
+        cmp::max(speed, 1)
+    }
+
+    /// How hurt a fighter looks, None if it can't be hurt.
+    pub fn health_state(&self, game: &Game) -> Option<&'static str> {
+        self.fighter.map(|fighter| {
+            let health = fighter.hp as f32 / self.max_hp(game) as f32;
+            if health >= 1.0 {
+                "unhurt"
+            } else if health >= 0.75 {
+                "lightly wounded"
+            } else if health >= 0.5 {
+                "wounded"
+            } else if health >= 0.25 {
+                "badly wounded"
+            } else {
+                "almost dead"
+            }
+        })
+    }
+
+    pub fn has_effect(&self, kind: EffectKind) -> bool {
+        self.effects.iter().any(|effect| effect.kind == kind)
+    }
+
+    /// Adds a status effect, stacking it with the same one if already there
+    /// and cancelling its opposite.
+    pub fn add_effect(&mut self, effect: StatusEffect) {
+        if let Some(opposite) = effect.kind.opposite() {
+            if self.has_effect(opposite) {
+                self.effects.retain(|e| e.kind != opposite);
+                return;
+            }
+        }
+        match self.effects.iter().position(|e| e.kind == effect.kind) {
+            Some(index) => self.effects[index].stack(effect),
+            None => self.effects.push(effect),
+        }
+    }
+
     pub fn get_all_equipped(&self, game: &Game) -> Vec<Equipment> {
         if self.name == "player" { // TODO
             game.inventory.iter().filter(|item| item.equipment.map_or(false, |e| e.equipped))
@@ -198,6 +255,12 @@ pub struct Fighter {
     pub base_defense: i32,
     pub base_power: i32,
     pub xp: i32,
+    pub base_speed: i32,
+    pub energy: i32,
+    // healing gathered over the turns, hit points come back by themselves
+    pub regen: i32,
+    // what its hits do besides damage, None in saves from before there was any
+    pub hit_effect: Option<StatusEffect>,
     pub on_death: DeathCallback,
 }
 
@@ -207,8 +270,59 @@ pub enum Item {
     Lightning,
     Confuse,
     Fireball,
-    Sword,
-    Shield,
+    Regenerate,
+    Haste,
+    Slow,
+    Blind,
+    Paralyse,
+    Equipment,
+}
+
+/// What an item has to be aimed at when used.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Targeting {
+    Nothing,
+    // a monster within range
+    Monster(f32),
+    // any tile in sight, within range if there is one, hitting everything
+    // within the radius around it
+    Tile(Option<f32>, i32),
+}
+
+impl Item {
+    pub fn targeting(&self, config: &Config) -> Targeting {
+        match *self {
+            Item::Confuse => Targeting::Monster(config.confuse_range as f32),
+            Item::Slow => Targeting::Monster(config.slow_range as f32),
+            Item::Blind => Targeting::Monster(config.blind_range as f32),
+            Item::Paralyse => Targeting::Monster(config.paralyse_range as f32),
+            Item::Fireball => Targeting::Tile(None, config.fireball_radius),
+            _ => Targeting::Nothing,
+        }
+    }
+
+    /// What the item does, shown when looking at it.
+    pub fn description(&self, config: &Config) -> String {
+        match *self {
+            Item::Heal => format!("Heals {} hit points.", config.heal_amount),
+            Item::Lightning => format!("Strikes the closest enemy within {} tiles for {} hit points.",
+                                       config.lightning_range, config.lightning_damage),
+            Item::Confuse => format!("Confuses an enemy within {} tiles for {} turns.",
+                                     config.confuse_range, config.confuse_num_turns),
+            Item::Fireball => format!("Burns everything within {} tiles of the target for {} hit points.",
+                                      config.fireball_radius, config.fireball_damage),
+            Item::Regenerate => format!("Heals {} hit points every turn for {} turns.",
+                                        config.regeneration_amount, config.regeneration_turns),
+            Item::Haste => format!("Makes you faster for {} turns.", config.haste_turns),
+            Item::Slow => format!("Slows an enemy within {} tiles down for {} turns.",
+                                  config.slow_range, config.slow_turns),
+            Item::Blind => format!("Blinds an enemy within {} tiles for {} turns.",
+                                   config.blind_range, config.blind_turns),
+            Item::Paralyse => format!("Paralyses an enemy within {} tiles for {} turns.",
+                                      config.paralyse_range, config.paralyse_turns),
+            Item::Equipment => "Can be equipped.".into(),
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, RustcDecodable, RustcEncodable)]
@@ -219,6 +333,21 @@ pub struct Equipment {
     pub max_hp_bonus: i32,
     pub power_bonus: i32,
     pub defense_bonus: i32,
+    pub speed_bonus: i32,
+}
+
+impl Equipment {
+    /// The bonuses it gives and where it's worn, e.g. "+2 power, on the left hand".
+    pub fn description(&self) -> String {
+        let bonuses = [(self.power_bonus, "power"), (self.defense_bonus, "defense"),
+                       (self.max_hp_bonus, "maximum HP"), (self.speed_bonus, "speed")];
+        let mut parts: Vec<String> = bonuses.iter()
+            .filter(|&&(bonus, _)| bonus != 0)
+            .map(|&(bonus, name)| format!("{:+} {}", bonus, name))
+            .collect();
+        parts.push(format!("on the {}", self.slot));
+        parts.join(", ")
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, RustcDecodable, RustcEncodable)]
@@ -238,12 +367,6 @@ impl ::std::fmt::Display for Slot {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
-pub enum MonsterType {
-    Orc,
-    Troll,
-}
-
 impl DeathCallback {
     fn callback(self, object: &mut Object, game: &mut Game) {
         let callback: fn(&mut Object, &mut Game) = match self {
@@ -257,7 +380,6 @@ impl DeathCallback {
 #[derive(Clone, Debug, PartialEq, RustcEncodable, RustcDecodable)]
 pub enum Ai {
     Basic,
-    Confused{previous_ai: Box<Ai>, num_turns: i32},
 }
 
 pub struct ObjectsManager {
@@ -266,28 +388,12 @@ pub struct ObjectsManager {
 
 impl ObjectsManager {
 
-    pub fn draw_clear(&self, con: &mut Offscreen) {
-        for object in &self.objects {
-            object.borrow().clear(con);
-        }
-    }
-
-    pub fn draw(&self, tcod: &mut Tcod, game: &mut Game) {
-        let mut to_draw: Vec<_> = self.objects.iter().map(|c| c.borrow()).filter(|o| {
-            tcod.fov.is_in_fov(o.x, o.y) || (o.always_visible && game.map[o.x as usize][o.y as usize].explored)
-        }).collect();
-        // sort so that non-blocking objects come first
-        to_draw.sort_by(|o1, o2| { o1.blocks.cmp(&o2.blocks) });
-        for object in &to_draw {
-            object.draw(&mut tcod.con);
-        }
-    }
-
     pub fn move_by(&mut self, id: usize, dx: i32, dy: i32, map: &Map) {
         let (x, y) = self.objects[id].borrow().pos();
         let new_x: i32 = x + dx;
         let new_y: i32 = y + dy;
-        if new_x < 0 || new_y < 0 || new_x >= MAP_WIDTH || new_y >= MAP_HEIGHT {
+        let (width, height) = map_size(map);
+        if new_x < 0 || new_y < 0 || new_x >= width || new_y >= height {
             return
         }
 
@@ -303,7 +409,7 @@ impl ObjectsManager {
         // make a vector
         let dx = target_x - x;
         let dy = target_y - y;
-        let distance = ((dx.pow(2) + dx.pow(2)) as f32).sqrt();
+        let distance = ((dx.pow(2) + dy.pow(2)) as f32).sqrt();
 
         // normalize to 1
         let dx = (dx as f32 / distance).round() as i32;
@@ -313,70 +419,203 @@ impl ObjectsManager {
     }
 
     pub fn player_move_or_attack(&mut self, dx: i32, dy: i32, game: &mut Game) {
+        let (dx, dy) = if self.objects[PLAYER].borrow().has_effect(EffectKind::Confusion) {
+            let mut rng = game.turn_rng(PLAYER);
+            (rng.gen_range(-1, 2), rng.gen_range(-1, 2))
+        } else {
+            (dx, dy)
+        };
         let (mut x, mut y) = self.objects[PLAYER].borrow().pos();
         x += dx;
         y += dy;
 
-        let target_id = self.objects.iter_mut().map(|c| c.borrow()).position(|object| {
-            object.fighter.is_some() && object.pos() == (x, y)
+        // a confused player may stumble in place, don't let it attack itself
+        let target_id = self.objects.iter().enumerate().position(|(id, c)| {
+            let object = c.borrow();
+            id != PLAYER && object.fighter.is_some() && object.pos() == (x, y)
         });
 
         match target_id {
             Some(target_id) => {
-                let (mut player, mut target) = (self.objects[PLAYER].borrow_mut(), self.objects[target_id].borrow_mut());
-                player.attack(target.deref_mut(), game);
+                {
+                    let (mut player, mut target) = (self.objects[PLAYER].borrow_mut(), self.objects[target_id].borrow_mut());
+                    player.attack(target.deref_mut(), game);
+                }
+                self.spend_energy(PLAYER, ATTACK_COST);
             },
             None => {
                 self.move_by(PLAYER, dx, dy, &game.map);
+                self.spend_energy(PLAYER, MOVE_COST);
             }
         }
     }
 
-    pub fn ai_take_turn(&mut self, monster_id: usize, game: &mut Game, fov_map: &FovMap) {
+    pub fn spend_energy(&mut self, id: usize, cost: i32) {
+        if let Some(fighter) = self.objects[id].borrow_mut().fighter.as_mut() {
+            fighter.energy -= cost;
+        }
+    }
+
+    fn can_act(&self, id: usize) -> bool {
+        self.objects[id].borrow().fighter.map_or(false, |f| f.energy >= ACTION_ENERGY)
+    }
+
+    pub fn ai_take_turn(&mut self, monster_id: usize, game: &mut Game, fov_map: &FovMap, config: &Config) {
+        let (paralysed, confused) = {
+            let monster = self.objects[monster_id].borrow();
+            (monster.has_effect(EffectKind::Paralysis), monster.has_effect(EffectKind::Confusion))
+        };
+        if paralysed {
+            self.spend_energy(monster_id, WAIT_COST);
+            return;
+        }
+        if confused {
+            self.ai_confused(monster_id, game);
+            return;
+        }
+
         let ai_option = self.objects[monster_id].borrow_mut().ai.take();
         if let Some(ai) = ai_option {
             let new_ai = match ai {
-                Ai::Basic => self.ai_basic(monster_id, game, fov_map),
-                Ai::Confused{previous_ai, num_turns} => self.ai_confused(monster_id, game, previous_ai, num_turns),
+                Ai::Basic => self.ai_basic(monster_id, game, fov_map, config),
             };
             self.objects[monster_id].borrow_mut().ai = Some(new_ai);
         }
     }
 
-    pub fn ai_turn(&mut self, game: &mut Game, fov_map: &FovMap) {
-        for id in 0..self.objects.len() {
-            if self.objects[id].borrow().ai.is_some() {
-                self.ai_take_turn(id, game, &fov_map);
+    /// Lets time pass until the player has gathered enough energy to act again.
+    /// Every tick each fighter gains energy by its speed, and monsters act as
+    /// soon as they have enough of it, so fast ones act more often than slow ones.
+    pub fn advance_time(&mut self, game: &mut Game, fov_map: &FovMap, config: &Config) {
+        loop {
+            for id in 0..self.objects.len() {
+                while self.objects[PLAYER].borrow().alive && self.objects[id].borrow().ai.is_some() && self.can_act(id) {
+                    self.ai_take_turn(id, game, &fov_map, config);
+                }
+            }
+
+            if !self.objects[PLAYER].borrow().alive {
+                break;
+            }
+            if self.can_act(PLAYER) {
+                if !self.objects[PLAYER].borrow().has_effect(EffectKind::Paralysis) {
+                    break;
+                }
+                // the player's turn passes without doing anything
+                self.spend_energy(PLAYER, WAIT_COST);
+            }
+
+            game.turn += 1;
+            game.log.set_turn(game.turn);
+            for id in 0..self.objects.len() {
+                self.tick_effects(id, game);
+                self.regenerate(id, game, config);
+            }
+            for cell in &self.objects {
+                let mut object = cell.borrow_mut();
+                let speed = object.speed(game);
+                if let Some(fighter) = object.fighter.as_mut() {
+                    fighter.energy += speed;
+                }
+            }
+        }
+    }
+
+    /// Applies the status effects of an object for one turn and removes
+    /// the ones that ran out.
+    fn tick_effects(&mut self, id: usize, game: &mut Game) {
+        let mut xp_gained = None;
+        {
+            let mut object = self.objects[id].borrow_mut();
+            let effects = object.effects.clone();
+            for effect in &effects {
+                if let Some(xp) = effect.on_tick(object.deref_mut(), game) {
+                    xp_gained = Some(xp);
+                }
+            }
+
+            for effect in object.effects.iter_mut() {
+                effect.turns -= 1;
+            }
+            let expired: Vec<_> = object.effects.iter().filter(|e| e.turns <= 0).map(|e| e.kind).collect();
+            object.effects.retain(|e| e.turns > 0);
+            for kind in expired {
+                if id == PLAYER {
+                    game.log.add(format!("You are no longer {}.", kind.adjective()), colors::LIGHT_GREY);
+                } else {
+                    game.log.add(format!("The {} is no longer {}!", object.name, kind.adjective()), colors::LIGHT_GREY);
+                }
+            }
+        }
+
+        // whoever dies from an effect gives experience to the player
+        if let Some(xp) = xp_gained {
+            if id != PLAYER {
+                if let Some(f) = self.objects[PLAYER].borrow_mut().fighter.as_mut() {
+                    f.xp += xp;
+                }
             }
         }
     }
 
-    fn ai_basic(&mut self, monster_id: usize, game: &mut Game, fov_map: &FovMap) -> Ai {
+    /// Heals a wounded fighter a little. It takes `full_regen_turns` turns
+    /// to heal completely, whatever the maximum hit points.
+    fn regenerate(&mut self, id: usize, game: &Game, config: &Config) {
+        let mut object = self.objects[id].borrow_mut();
+        if !object.alive {
+            return;
+        }
+        let max_hp = object.max_hp(game);
+        let healed = match object.fighter.as_mut() {
+            Some(fighter) => {
+                if fighter.hp >= max_hp {
+                    fighter.regen = 0;
+                    return;
+                }
+                fighter.regen += max_hp;
+                let healed = fighter.regen / config.full_regen_turns;
+                fighter.regen %= config.full_regen_turns;
+                healed
+            },
+            None => return,
+        };
+        object.heal(healed, game);
+    }
+
+    fn ai_basic(&mut self, monster_id: usize, game: &mut Game, fov_map: &FovMap, config: &Config) -> Ai {
         let (monster_x, monster_y) = self.objects[monster_id].borrow().pos();
+        let distance = self.objects[monster_id].borrow().distance_to(self.objects[PLAYER].borrow().deref());
+        // a blind monster only notices the player right next to it
+        let blind = self.objects[monster_id].borrow().has_effect(EffectKind::Blindness);
 
-        if fov_map.is_in_fov(monster_x, monster_y) {
-            let distance = self.objects[monster_id].borrow().distance_to(self.objects[PLAYER].borrow().deref());
+        if fov_map.is_in_fov(monster_x, monster_y) && (!blind || distance < 2.0) {
             if distance >= 2.0 {
-                // move towards player if far away
+                // move towards player if far away, walking around the walls
                 let (player_x, player_y) = self.objects[PLAYER].borrow().pos();
-                self.move_towards(monster_id, player_x, player_y, &game.map);
+                let step = pathfinding::next_step(&game.map, &self.objects, (monster_x, monster_y),
+                                                  (player_x, player_y), config.monster_path_length);
+                match step {
+                    Some((x, y)) => self.move_by(monster_id, x - monster_x, y - monster_y, &game.map),
+                    None => self.move_towards(monster_id, player_x, player_y, &game.map),
+                }
+                self.spend_energy(monster_id, MOVE_COST);
             } else {
-                let (mut player, mut monster) = (self.objects[PLAYER].borrow_mut(), self.objects[monster_id].borrow_mut());
-                monster.attack(player.deref_mut(), game);
+                {
+                    let (mut player, mut monster) = (self.objects[PLAYER].borrow_mut(), self.objects[monster_id].borrow_mut());
+                    monster.attack(player.deref_mut(), game);
+                }
+                self.spend_energy(monster_id, ATTACK_COST);
             }
+        } else {
+            self.spend_energy(monster_id, WAIT_COST);
         }
         Ai::Basic
     }
 
-    fn ai_confused(&mut self, monster_id: usize, game: &mut Game, previous_ai: Box<Ai>, num_turns: i32) -> Ai 
-    {
-        if num_turns >= 0 {
-            self.move_by(monster_id, rand::thread_rng().gen_range(-1, 2), rand::thread_rng().gen_range(-1, 2), &game.map);
-            Ai::Confused{previous_ai: previous_ai, num_turns: num_turns - 1}
-        } else {
-            game.log.add(format!("The {} is no longer confused!", self.objects[monster_id].borrow().name), colors::RED);
-            *previous_ai
-        }
+    fn ai_confused(&mut self, monster_id: usize, game: &mut Game) {
+        let mut rng = game.turn_rng(monster_id);
+        self.move_by(monster_id, rng.gen_range(-1, 2), rng.gen_range(-1, 2), &game.map);
+        self.spend_energy(monster_id, MOVE_COST);
     }
 }
 
@@ -385,6 +624,7 @@ fn player_death(player: &mut Object, game: &mut Game) {
 
     player.char = '%';
     player.color = colors::DARK_RED;
+    player.effects.clear();
 }
 
 fn monster_death(monster: &mut Object, game: &mut Game) {
@@ -398,17 +638,21 @@ fn monster_death(monster: &mut Object, game: &mut Game) {
     monster.blocks = false;
     monster.fighter = None;
     monster.ai = None;
+    monster.effects.clear();
     monster.name = format!("remains of {}", monster.name);
 }
 
-pub fn pick_item_up(object_id: usize, object_manager: &mut ObjectsManager, game: &mut Game) {
+/// Returns true if the item was picked up.
+pub fn pick_item_up(object_id: usize, object_manager: &mut ObjectsManager, game: &mut Game) -> bool {
     if game.inventory.len() >= MAX_INVENTORY_SIZE as usize {
         game.log.add(format!("Your inventory is full, cannot pick up {}.", object_manager.objects[object_id].borrow().deref().name), colors::RED);
+        false
     } else {
         let cell = object_manager.objects.swap_remove(object_id);
         let item = cell.into_inner();
         game.log.add(format!("You picked up a {}!", item.name), colors::GREEN);
         game.inventory.push(item);
+        true
     }
 }
 
@@ -418,7 +662,11 @@ pub enum UseResult {
     Cancelled,
 }
 
-pub fn use_item(inventory_id: usize, object_manager: &mut ObjectsManager, game: &mut Game, tcod: &mut Tcod) 
+/// Uses an item from the inventory. `target` is the tile it's aimed at,
+/// None when it doesn't need one or the player didn't choose any.
+/// Returns true if the item was used.
+pub fn use_item(inventory_id: usize, target: Option<(i32, i32)>, object_manager: &mut ObjectsManager, game: &mut Game,
+                fov_map: &FovMap, config: &Config) -> bool
 {
     let item = game.inventory[inventory_id].item;
     
@@ -427,22 +675,28 @@ pub fn use_item(inventory_id: usize, object_manager: &mut ObjectsManager, game:
         Some(Item::Lightning) => cast_lightning,
         Some(Item::Confuse) => cast_confuse,
         Some(Item::Fireball) => cast_fireball,
-        Some(Item::Sword) => toggle_equipment,
-        Some(Item::Shield) => toggle_equipment,
+        Some(Item::Regenerate) => cast_regenerate,
+        Some(Item::Haste) => cast_haste,
+        Some(Item::Slow) => cast_slow,
+        Some(Item::Blind) => cast_blind,
+        Some(Item::Paralyse) => cast_paralyse,
+        Some(Item::Equipment) => toggle_equipment,
         None => {
             game.log.add(format!("The {} cannot be used.", game.inventory[inventory_id].name), colors::WHITE);
-            return
+            return false;
         },
     };
 
-    match on_use(inventory_id, object_manager, game, tcod) {
+    match on_use(inventory_id, target, object_manager, game, fov_map, config) {
         UseResult::UsedUp => {
             // destroy after use
             game.inventory.remove(inventory_id);
+            true
         },
-        UseResult::UsedAndKept => {}, // do nothing
+        UseResult::UsedAndKept => true,
         UseResult::Cancelled => {
             game.log.add("Cancelled", colors::WHITE);
+            false
         }
     }
 }
@@ -458,11 +712,14 @@ pub fn drop_item(inventory_id: usize, object_manager: &mut ObjectsManager, game:
         let player = object_manager.objects[PLAYER].borrow();
         item.set_pos(player.x, player.y);
     }
+    item.dropped = true;
     game.log.add(format!("You dropped a {}.", item.name), colors::YELLOW);
     object_manager.objects.push(RefCell::new(item));
 }
 
-fn cast_heal(_inventory_id: usize, object_manager: &mut ObjectsManager, game: &mut Game, tcod: &mut Tcod) -> UseResult {
+fn cast_heal(_inventory_id: usize, _target: Option<(i32, i32)>, object_manager: &mut ObjectsManager, game: &mut Game,
+             _fov_map: &FovMap, config: &Config) -> UseResult
+{
     let mut player = object_manager.objects[PLAYER].borrow_mut();
     if let Some(fighter) = player.fighter {
         if fighter.hp == player.max_hp(game) {
@@ -471,22 +728,24 @@ fn cast_heal(_inventory_id: usize, object_manager: &mut ObjectsManager, game: &m
         }
 
         game.log.add("Your wounds start to feel better!", colors::LIGHT_VIOLET);
-        player.heal(HEAL_AMOUNT, game);
+        player.heal(config.heal_amount, game);
         return UseResult::UsedUp;
     }
     UseResult::Cancelled
 }
 
-fn cast_lightning(_inventory_id: usize, object_manager: &mut ObjectsManager, game: &mut Game, tcod: &mut Tcod) -> UseResult {
+fn cast_lightning(_inventory_id: usize, _target: Option<(i32, i32)>, object_manager: &mut ObjectsManager, game: &mut Game,
+                  fov_map: &FovMap, config: &Config) -> UseResult
+{
     // find the closest enemy
-    let monster_id = closest_monster(LIGHTNING_RANGE, object_manager, tcod);
+    let monster_id = closest_monster(config.lightning_range, object_manager, fov_map);
     if let Some(monster_id) = monster_id {
         let mut monster = object_manager.objects[monster_id].borrow_mut();
         game.log.add(format!("A lightning bolt strikes the {} with a loud thunder! \
                                         The damage is {} hit points.", 
-                                monster.name, LIGHTNING_DAMAGE),
+                                monster.name, config.lightning_damage),
                 colors::LIGHT_BLUE);
-        if let Some(xp) = monster.take_damage(LIGHTNING_DAMAGE, game) {
+        if let Some(xp) = monster.take_damage(config.lightning_damage, game) {
             // add exp to the player
             if let Some(f) = object_manager.objects[PLAYER].borrow_mut().fighter.as_mut() {
                 f.xp += xp;
@@ -499,19 +758,16 @@ fn cast_lightning(_inventory_id: usize, object_manager: &mut ObjectsManager, gam
     }
 }
 
-fn cast_confuse(_inventory_id: usize, object_manager: &mut ObjectsManager, game: &mut Game, tcod: &mut Tcod) -> UseResult {
-    game.log.add("Left-click an enemy to confuse it, or right-click to cancel.",
-        colors::LIGHT_CYAN);
-    let monster_id = target_monster(tcod, object_manager, game, Some(CONFUSE_RANGE as f32));
+fn cast_confuse(_inventory_id: usize, target: Option<(i32, i32)>, object_manager: &mut ObjectsManager, game: &mut Game,
+                fov_map: &FovMap, config: &Config) -> UseResult
+{
+    let monster_id = target.and_then(|(x, y)| {
+        monster_at(x, y, Some(config.confuse_range as f32), object_manager, fov_map)
+    });
 
     if let Some(monster_id) = monster_id {
         let mut monster = object_manager.objects[monster_id].borrow_mut();
-        // replace old ai
-        let old_ai = monster.ai.take().unwrap_or(Ai::Basic);
-        monster.ai = Some(Ai::Confused {
-            previous_ai: Box::new(old_ai),
-            num_turns: CONFUSE_NUM_TURNS,
-        });
+        monster.add_effect(StatusEffect::new(EffectKind::Confusion, config.confuse_num_turns, 0));
         game.log.add(format!("The eyes of {} look vacant, as he starts to stumble around!",
                                             monster.name),
                 colors::LIGHT_GREEN);
@@ -522,23 +778,23 @@ fn cast_confuse(_inventory_id: usize, object_manager: &mut ObjectsManager, game:
     }
 }
 
-fn cast_fireball(_inventory_id: usize, object_manager: &mut ObjectsManager, game: &mut Game, tcod: &mut Tcod) -> UseResult {
-    game.log.add("Left-click a target tile for the fireball, or right-click to cancel.",
-            colors::LIGHT_CYAN);
-    let (x, y) = match target_tile(tcod, object_manager, game, None) {
-        Some(tile_pos) => tile_pos,
-        None => return UseResult::Cancelled,
+fn cast_fireball(_inventory_id: usize, target: Option<(i32, i32)>, object_manager: &mut ObjectsManager, game: &mut Game,
+                 fov_map: &FovMap, config: &Config) -> UseResult
+{
+    let (x, y) = match target {
+        Some((x, y)) if fov_map.is_in_fov(x, y) => (x, y),
+        _ => return UseResult::Cancelled,
     };
-    game.log.add(format!("The fireball explodes, burning everything within {} tiles!", FIREBALL_RADIUS),
+    game.log.add(format!("The fireball explodes, burning everything within {} tiles!", config.fireball_radius),
             colors::ORANGE);
 
     let mut xp_to_gain = 0;
     for (id, cell) in object_manager.objects.iter_mut().enumerate() {
         let mut obj = cell.borrow_mut();
-        if obj.distance(x, y) <= FIREBALL_RADIUS as f32 && obj.fighter.is_some() {
-            game.log.add(format!("The {} gets burned for {} hit points.", obj.name, FIREBALL_DAMAGE),
+        if obj.distance(x, y) <= config.fireball_radius as f32 && obj.fighter.is_some() {
+            game.log.add(format!("The {} gets burned for {} hit points.", obj.name, config.fireball_damage),
                     colors::ORANGE);
-            if let Some(xp) = obj.take_damage(FIREBALL_DAMAGE, game) {
+            if let Some(xp) = obj.take_damage(config.fireball_damage, game) {
                 if id != PLAYER {
                     xp_to_gain += xp;
                 }
@@ -550,7 +806,66 @@ fn cast_fireball(_inventory_id: usize, object_manager: &mut ObjectsManager, game
     UseResult::UsedUp
 }
 
-fn toggle_equipment(inventory_id: usize, _object_manager: &mut ObjectsManager, game: &mut Game, _tcod: &mut Tcod) -> UseResult {
+fn cast_regenerate(_inventory_id: usize, _target: Option<(i32, i32)>, object_manager: &mut ObjectsManager, game: &mut Game,
+                   _fov_map: &FovMap, config: &Config) -> UseResult
+{
+    let effect = StatusEffect::new(EffectKind::Regeneration, config.regeneration_turns, config.regeneration_amount);
+    object_manager.objects[PLAYER].borrow_mut().add_effect(effect);
+    game.log.add("Your wounds start to close.", EffectKind::Regeneration.color());
+    UseResult::UsedUp
+}
+
+fn cast_haste(_inventory_id: usize, _target: Option<(i32, i32)>, object_manager: &mut ObjectsManager, game: &mut Game,
+              _fov_map: &FovMap, config: &Config) -> UseResult
+{
+    object_manager.objects[PLAYER].borrow_mut().add_effect(StatusEffect::new(EffectKind::Haste, config.haste_turns, 0));
+    game.log.add("You feel quick.", EffectKind::Haste.color());
+    UseResult::UsedUp
+}
+
+fn cast_slow(_inventory_id: usize, target: Option<(i32, i32)>, object_manager: &mut ObjectsManager, game: &mut Game,
+             fov_map: &FovMap, config: &Config) -> UseResult
+{
+    cast_on_monster(target, config.slow_range, StatusEffect::new(EffectKind::Slow, config.slow_turns, 0),
+                    object_manager, game, fov_map)
+}
+
+fn cast_blind(_inventory_id: usize, target: Option<(i32, i32)>, object_manager: &mut ObjectsManager, game: &mut Game,
+              fov_map: &FovMap, config: &Config) -> UseResult
+{
+    cast_on_monster(target, config.blind_range, StatusEffect::new(EffectKind::Blindness, config.blind_turns, 0),
+                    object_manager, game, fov_map)
+}
+
+fn cast_paralyse(_inventory_id: usize, target: Option<(i32, i32)>, object_manager: &mut ObjectsManager, game: &mut Game,
+                 fov_map: &FovMap, config: &Config) -> UseResult
+{
+    cast_on_monster(target, config.paralyse_range, StatusEffect::new(EffectKind::Paralysis, config.paralyse_turns, 0),
+                    object_manager, game, fov_map)
+}
+
+/// Gives an effect to the monster on the target tile, if there's one within range.
+fn cast_on_monster(target: Option<(i32, i32)>, range: i32, effect: StatusEffect, object_manager: &mut ObjectsManager,
+                   game: &mut Game, fov_map: &FovMap) -> UseResult
+{
+    let monster_id = target.and_then(|(x, y)| {
+        monster_at(x, y, Some(range as f32), object_manager, fov_map)
+    });
+
+    if let Some(monster_id) = monster_id {
+        let mut monster = object_manager.objects[monster_id].borrow_mut();
+        monster.add_effect(effect);
+        game.log.add(format!("The {} is {}!", monster.name, effect.kind.adjective()), effect.kind.color());
+        UseResult::UsedUp
+    } else {
+        game.log.add("No enemy is close enough to strike", colors::RED);
+        UseResult::Cancelled
+    }
+}
+
+fn toggle_equipment(inventory_id: usize, _target: Option<(i32, i32)>, _object_manager: &mut ObjectsManager, game: &mut Game,
+                    _fov_map: &FovMap, _config: &Config) -> UseResult
+{
     let equipment = match game.inventory[inventory_id].equipment {
         Some(equipment) => equipment,
         None => return UseResult::Cancelled,
@@ -575,7 +890,7 @@ fn get_equipped_in_slot(slot: Slot, inventory: &[Object]) -> Option<usize> {
     None
 }
 
-fn closest_monster(max_range: i32, object_manager: &mut ObjectsManager, tcod: &mut Tcod) -> Option<usize> {
+fn closest_monster(max_range: i32, object_manager: &mut ObjectsManager, fov_map: &FovMap) -> Option<usize> {
     let mut closest_enemy = None;
     let mut closest_dist = (max_range + 1) as f32; // starts with slighty more than max range
 
@@ -583,7 +898,7 @@ fn closest_monster(max_range: i32, object_manager: &mut ObjectsManager, tcod: &m
         let object = cell.borrow();
 
         if (id != PLAYER) && object.fighter.is_some() && object.ai.is_some() 
-            && tcod.fov.is_in_fov(object.x, object.y) 
+            && fov_map.is_in_fov(object.x, object.y) 
         {
             let dist = object_manager.objects[PLAYER].borrow().distance_to(object.deref());
             if dist < closest_dist {
@@ -594,3 +909,134 @@ fn closest_monster(max_range: i32, object_manager: &mut ObjectsManager, tcod: &m
     }
     closest_enemy
 }
+
+/// The monster standing on a tile the player can see, if it's within range.
+pub fn monster_at(x: i32, y: i32, max_range: Option<f32>, object_manager: &ObjectsManager, fov_map: &FovMap) -> Option<usize> {
+    if !fov_map.is_in_fov(x, y) {
+        return None;
+    }
+    let in_range = max_range.map_or(true, |range| object_manager.objects[PLAYER].borrow().distance(x, y) <= range);
+    if !in_range {
+        return None;
+    }
+    object_manager.objects.iter().enumerate().position(|(id, cell)| {
+        let obj = cell.borrow();
+        id != PLAYER && obj.pos() == (x, y) && obj.fighter.is_some()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tile::Tile;
+    use super::*;
+
+    fn fighter(speed: i32) -> Object {
+        let mut object = Object::new(1, 1, 'o', "orc", colors::GREEN, true);
+        object.alive = true;
+        object.fighter = Some(Fighter{base_max_hp: 10, hp: 10, base_defense: 0, base_power: 0, xp: 0,
+                                      base_speed: speed, energy: 0, regen: 0, hit_effect: None,
+                                      on_death: DeathCallback::Monster});
+        object
+    }
+
+    fn game() -> Game {
+        Game {
+            map: vec![vec![Tile::empty(); 10]; 10],
+            log: Messages::new(),
+            inventory: vec![],
+            dungeon_level: 1,
+            levels: vec![],
+            seed: 1,
+            turn: 0,
+            mode: GameMode::Explore,
+            actions_taken: 0,
+        }
+    }
+
+    fn energy(object_manager: &ObjectsManager, id: usize) -> i32 {
+        object_manager.objects[id].borrow().fighter.unwrap().energy
+    }
+
+    #[test]
+    fn advance_time_lets_fast_monsters_act_more_often() {
+        let mut game = game();
+        // nothing is in the player's view, monsters only wait
+        let fov_map = FovMap::new(10, 10);
+        let (mut fast, mut slow) = (fighter(200), fighter(50));
+        fast.ai = Some(Ai::Basic);
+        slow.ai = Some(Ai::Basic);
+        let mut object_manager = ObjectsManager{objects: vec![RefCell::new(fighter(100)), RefCell::new(fast),
+                                                              RefCell::new(slow)]};
+
+        object_manager.advance_time(&mut game, &fov_map, &Config::default());
+        assert_eq!(game.turn, 1);
+        assert_eq!(energy(&object_manager, PLAYER), 100);
+        // waited twice already, the slow one not yet
+        assert_eq!(energy(&object_manager, 1), 0);
+        assert_eq!(energy(&object_manager, 2), 50);
+
+        object_manager.spend_energy(PLAYER, WAIT_COST);
+        object_manager.advance_time(&mut game, &fov_map, &Config::default());
+        assert_eq!(game.turn, 2);
+        assert_eq!(energy(&object_manager, 1), 0);
+        assert_eq!(energy(&object_manager, 2), 0);
+    }
+
+    #[test]
+    fn advance_time_ends_for_a_player_without_speed() {
+        let mut game = game();
+        let fov_map = FovMap::new(10, 10);
+        let mut object_manager = ObjectsManager{objects: vec![RefCell::new(fighter(0))]};
+        object_manager.advance_time(&mut game, &fov_map, &Config::default());
+        assert_eq!(game.turn, ACTION_ENERGY as u32);
+    }
+
+    #[test]
+    fn add_effect_stacks_the_same_effect() {
+        let mut orc = fighter(100);
+        orc.add_effect(StatusEffect::new(EffectKind::Poison, 5, 1));
+        orc.add_effect(StatusEffect::new(EffectKind::Poison, 3, 2));
+        orc.add_effect(StatusEffect::new(EffectKind::Confusion, 4, 0));
+        orc.add_effect(StatusEffect::new(EffectKind::Confusion, 4, 0));
+        assert_eq!(orc.effects, vec![StatusEffect::new(EffectKind::Poison, 5, 3),
+                                     StatusEffect::new(EffectKind::Confusion, 8, 0)]);
+    }
+
+    #[test]
+    fn add_effect_cancels_the_opposite_effect() {
+        let mut orc = fighter(100);
+        orc.add_effect(StatusEffect::new(EffectKind::Haste, 10, 0));
+        assert_eq!(orc.speed(&game()), 150);
+        orc.add_effect(StatusEffect::new(EffectKind::Slow, 10, 0));
+        assert!(orc.effects.is_empty());
+        assert_eq!(orc.speed(&game()), 100);
+    }
+
+    #[test]
+    fn tick_effects_wears_effects_off() {
+        let mut game = game();
+        let mut orc = fighter(100);
+        orc.add_effect(StatusEffect::new(EffectKind::Blindness, 2, 0));
+        let mut object_manager = ObjectsManager{objects: vec![RefCell::new(fighter(100)), RefCell::new(orc)]};
+        object_manager.tick_effects(1, &mut game);
+        assert!(object_manager.objects[1].borrow().has_effect(EffectKind::Blindness));
+        object_manager.tick_effects(1, &mut game);
+        assert!(!object_manager.objects[1].borrow().has_effect(EffectKind::Blindness));
+    }
+
+    #[test]
+    fn poison_kills_and_gives_experience() {
+        let mut game = game();
+        let mut orc = fighter(100);
+        orc.fighter.as_mut().unwrap().xp = 35;
+        orc.add_effect(StatusEffect::new(EffectKind::Poison, 20, 4));
+        let mut object_manager = ObjectsManager{objects: vec![RefCell::new(fighter(100)), RefCell::new(orc)]};
+        for _ in 0..2 {
+            object_manager.tick_effects(1, &mut game);
+        }
+        assert_eq!(object_manager.objects[1].borrow().fighter.unwrap().hp, 2);
+        object_manager.tick_effects(1, &mut game);
+        assert!(!object_manager.objects[1].borrow().alive);
+        assert_eq!(object_manager.objects[PLAYER].borrow().fighter.unwrap().xp, 35);
+    }
+}
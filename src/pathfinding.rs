@@ -0,0 +1,230 @@
+use std::cell::RefCell;
+use std::cmp::{self, Ordering};
+use std::collections::{BinaryHeap, VecDeque};
+
+use map::*;
+use object::*;
+
+/// Tile waiting in the A* open set, ordered so the heap pops the lowest cost first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Node {
+    pos: (i32, i32),
+    // steps taken so far plus estimated steps left
+    cost: i32,
+    estimate: i32,
+}
+
+impl Ord for Node {
+    fn cmp(&self, other: &Node) -> Ordering {
+        // on equal cost, prefer the tiles closer to the goal
+        match other.cost.cmp(&self.cost) {
+            Ordering::Equal => other.estimate.cmp(&self.estimate),
+            ordering => ordering,
+        }
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Node) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Number of moves between two tiles when diagonal moves are allowed.
+fn moves_between((x1, y1): (i32, i32), (x2, y2): (i32, i32)) -> i32 {
+    cmp::max((x1 - x2).abs(), (y1 - y2).abs())
+}
+
+/// Shortest path between two tiles found with A*, going around walls and
+/// blocking objects. The path doesn't contain `from` and ends with `to`,
+/// which may itself be blocked (e.g. by the player being chased).
+/// Returns None if there is no path of at most `max_length` moves.
+pub fn find_path(map: &Map, objects: &[RefCell<Object>], from: (i32, i32), to: (i32, i32),
+                 max_length: i32) -> Option<Vec<(i32, i32)>>
+{
+    if moves_between(from, to) > max_length {
+        return None;
+    }
+
+    // no path of at most max_length moves leaves this box around `from`,
+    // so only it is searched
+    let (width, height) = map_size(map);
+    let (left, top) = (cmp::max(from.0 - max_length, 0), cmp::max(from.1 - max_length, 0));
+    let (right, bottom) = (cmp::min(from.0 + max_length, width - 1), cmp::min(from.1 + max_length, height - 1));
+    let (box_width, box_height) = ((right - left + 1) as usize, (bottom - top + 1) as usize);
+    let cell = |(x, y): (i32, i32)| ((x - left) as usize, (y - top) as usize);
+
+    let mut blocked_by_object = vec![vec![false; box_height]; box_width];
+    for object in objects.iter().map(|c| c.borrow()) {
+        if object.blocks && object.x >= left && object.x <= right && object.y >= top && object.y <= bottom {
+            let (bx, by) = cell(object.pos());
+            blocked_by_object[bx][by] = true;
+        }
+    }
+
+    let mut steps = vec![vec![i32::max_value(); box_height]; box_width];
+    let mut came_from = vec![vec![None; box_height]; box_width];
+    let mut open = BinaryHeap::new();
+
+    let (fx, fy) = cell(from);
+    steps[fx][fy] = 0;
+    open.push(Node { pos: from, cost: moves_between(from, to), estimate: moves_between(from, to) });
+
+    while let Some(Node { pos: (x, y), .. }) = open.pop() {
+        if (x, y) == to {
+            let mut path = vec![to];
+            let (mut cx, mut cy) = cell(to);
+            while let Some(previous) = came_from[cx][cy] {
+                if previous == from {
+                    break;
+                }
+                path.push(previous);
+                let (px, py) = cell(previous);
+                cx = px;
+                cy = py;
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        let (bx, by) = cell((x, y));
+        let next_steps = steps[bx][by] + 1;
+        if next_steps > max_length {
+            continue;
+        }
+
+        for dx in -1..2 {
+            for dy in -1..2 {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < left || ny < top || nx > right || ny > bottom {
+                    continue;
+                }
+                let (bx, by) = cell((nx, ny));
+                let passable = !map[nx as usize][ny as usize].blocked &&
+                    (!blocked_by_object[bx][by] || (nx, ny) == to);
+                if passable && next_steps < steps[bx][by] {
+                    steps[bx][by] = next_steps;
+                    came_from[bx][by] = Some((x, y));
+                    let estimate = moves_between((nx, ny), to);
+                    open.push(Node { pos: (nx, ny), cost: next_steps + estimate, estimate: estimate });
+                }
+            }
+        }
+    }
+    None
+}
+
+/// The first tile to step on when walking from one tile to another.
+pub fn next_step(map: &Map, objects: &[RefCell<Object>], from: (i32, i32), to: (i32, i32),
+                 max_length: i32) -> Option<(i32, i32)>
+{
+    find_path(map, objects, from, to, max_length).and_then(|path| path.first().cloned())
+}
+
+/// Shortest path from a tile to the closest one `is_goal` accepts, only
+/// going through the tiles `passable` accepts. The path doesn't contain
+/// `from` and is empty if `from` is a goal itself. Returns None if no goal
+/// can be reached.
+pub fn path_to_closest<P, G>(map: &Map, from: (i32, i32), passable: P, is_goal: G) -> Option<Vec<(i32, i32)>>
+    where P: Fn(i32, i32) -> bool, G: Fn(i32, i32) -> bool
+{
+    let (width, height) = map_size(map);
+    let mut came_from: Vec<Vec<Option<(i32, i32)>>> = vec![vec![None; height as usize]; width as usize];
+    let mut queue = VecDeque::new();
+    came_from[from.0 as usize][from.1 as usize] = Some(from);
+    queue.push_back(from);
+
+    // every move costs the same, so the first goal found is the closest
+    while let Some((x, y)) = queue.pop_front() {
+        if is_goal(x, y) {
+            let mut path = vec![];
+            let mut current = (x, y);
+            while current != from {
+                path.push(current);
+                current = came_from[current.0 as usize][current.1 as usize].unwrap();
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        for dx in -1..2 {
+            for dy in -1..2 {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= width || ny >= height {
+                    continue;
+                }
+                if came_from[nx as usize][ny as usize].is_none() && passable(nx, ny) {
+                    came_from[nx as usize][ny as usize] = Some((x, y));
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use tcod::colors;
+    use tile::Tile;
+    use super::*;
+
+    /// An open map with a wall at x = 5 from the top down to y = 9.
+    fn walled_map() -> Map {
+        let mut map = vec![vec![Tile::empty(); 20]; 20];
+        for tile in &mut map[5][..10] {
+            *tile = Tile::wall();
+        }
+        map
+    }
+
+    fn player() -> Vec<RefCell<Object>> {
+        vec![RefCell::new(Object::new(2, 2, '@', "player", colors::WHITE, true))]
+    }
+
+    #[test]
+    fn find_path_goes_around_walls() {
+        let map = walled_map();
+        let path = find_path(&map, &player(), (2, 2), (8, 2), 30).unwrap();
+        assert_eq!(path.last(), Some(&(8, 2)));
+        assert!(!path.contains(&(2, 2)));
+        assert!(path.iter().all(|&(x, y)| !map[x as usize][y as usize].blocked));
+        // down past the end of the wall at y = 9 and back up
+        assert_eq!(path.len(), 16);
+    }
+
+    #[test]
+    fn find_path_gives_up_on_long_paths() {
+        let map = walled_map();
+        assert!(find_path(&map, &player(), (2, 2), (8, 2), 15).is_none());
+        assert!(find_path(&map, &player(), (2, 2), (19, 19), 5).is_none());
+    }
+
+    #[test]
+    fn find_path_goes_around_blocking_objects() {
+        let map = vec![vec![Tile::empty(); 3]; 10];
+        let mut objects = player();
+        objects.push(RefCell::new(Object::new(4, 1, 'o', "orc", colors::GREEN, true)));
+        let path = find_path(&map, &objects, (2, 1), (6, 1), 10).unwrap();
+        assert!(!path.contains(&(4, 1)));
+        assert_eq!(path.len(), 4);
+    }
+
+    #[test]
+    fn path_to_closest_finds_the_closest_goal() {
+        let map = walled_map();
+        let passable = |x: i32, y: i32| !map[x as usize][y as usize].blocked;
+        let path = path_to_closest(&map, (2, 2), passable, |x, _| x == 0 || x == 8).unwrap();
+        assert_eq!(path.len(), 2);
+        assert_eq!(path[1].0, 0);
+        assert_eq!(path_to_closest(&map, (2, 2), passable, |x, y| (x, y) == (2, 2)), Some(vec![]));
+    }
+
+    #[test]
+    fn path_to_closest_only_goes_through_passable_tiles() {
+        let map = walled_map();
+        // only the left of the wall can be walked on
+        let path = path_to_closest(&map, (2, 2), |x, _| x < 5, |x, _| x == 8);
+        assert!(path.is_none());
+    }
+}
@@ -0,0 +1,284 @@
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+use std::error::Error;
+use rustc_serialize::json::{self, Json};
+
+use config::*;
+use world::{World, Action};
+use game::GameMode;
+use data::GameData;
+
+/// A recorded game: the dungeon seed and every action the player took.
+/// Playing the actions again on a new game with the same seed, settings and
+/// data files gives back exactly the same game, turn by turn.
+///
+/// Replay files hold the header on the first line, then one action per line,
+/// so the game can add to them as it is played.
+#[derive(Debug)]
+pub struct Replay {
+    pub header: ReplayHeader,
+    pub actions: Vec<Action>,
+}
+
+/// The first line of a replay file.
+#[derive(Debug, RustcEncodable, RustcDecodable)]
+pub struct ReplayHeader {
+    pub seed: u32,
+    // None for the replays recorded before the setup was
+    pub setup: Option<ReplaySetup>,
+}
+
+/// What the game was played with besides the seed.
+#[derive(Clone, Debug, RustcEncodable, RustcDecodable)]
+pub struct ReplaySetup {
+    pub settings: Config,
+    // a hash of each data file changing what the actions do, the key
+    // bindings don't
+    pub data_files: Vec<(String, u64)>,
+}
+
+impl ReplaySetup {
+    /// The given settings and the data files as they are now.
+    pub fn current(settings: Config) -> Result<Self, Box<Error>> {
+        let mut data_files = vec![];
+        for path in &[MONSTERS_FILE, ITEMS_FILE] {
+            data_files.push((path.to_string(), try! { hash_file(path) }));
+        }
+        Ok(ReplaySetup { settings: settings, data_files: data_files })
+    }
+
+    /// The names of the settings and data files that are not the same in both.
+    pub fn differences(&self, other: &ReplaySetup) -> Vec<String> {
+        let settings = |config: &Config| {
+            Json::from_str(&json::encode(config).expect("settings can always be encoded")).unwrap()
+        };
+        let (ours, theirs) = (settings(&self.settings), settings(&other.settings));
+        let mut differences: Vec<String> = match (ours.as_object(), theirs.as_object()) {
+            (Some(ours), Some(theirs)) => {
+                ours.iter()
+                    .filter(|&(name, value)| theirs.get(name) != Some(value))
+                    .map(|(name, _)| name.clone())
+                    .collect()
+            },
+            _ => vec![],
+        };
+        for file in &self.data_files {
+            if !other.data_files.contains(file) {
+                differences.push(file.0.clone());
+            }
+        }
+        differences
+    }
+}
+
+/// FNV-1a hash of a file, the same on every build unlike the std hashers.
+fn hash_file(path: &str) -> io::Result<u64> {
+    let mut bytes = vec![];
+    try! { try! { File::open(path) }.read_to_end(&mut bytes) };
+    Ok(bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3)))
+}
+
+impl ReplayHeader {
+    fn parse(line: &str) -> Result<Self, Box<Error>> {
+        // replays used to start with the seed alone
+        if let Ok(seed) = line.trim().parse() {
+            return Ok(ReplayHeader { seed: seed, setup: None });
+        }
+        Ok(try! { json::decode(line) })
+    }
+}
+
+impl Replay {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<Error>> {
+        let mut recorder = try! { Recorder::create(path, &self.header) };
+        for action in &self.actions {
+            try! { recorder.record(*action) };
+        }
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<Error>> {
+        #[derive(RustcDecodable)]
+        struct OldReplay {
+            seed: u32,
+            actions: Vec<Action>,
+        }
+
+        let mut text = String::new();
+        let mut file = try! { File::open(path) };
+        try! { file.read_to_string(&mut text) };
+        // replays used to be a single JSON object
+        if let Ok(replay) = json::decode::<OldReplay>(&text) {
+            return Ok(Replay { header: ReplayHeader { seed: replay.seed, setup: None }, actions: replay.actions });
+        }
+        let mut lines = text.lines();
+        let header = match lines.next() {
+            Some(line) => try! { ReplayHeader::parse(line) },
+            None => return Err("The replay is empty.".into()),
+        };
+        let mut actions = vec![];
+        for line in lines {
+            actions.push(try! { json::decode::<Action>(line) });
+        }
+        Ok(Replay { header: header, actions: actions })
+    }
+
+    /// Tells why the replay can't be played back with the game data, if it
+    /// was recorded with other settings or data files.
+    pub fn check(&self, data: &GameData) -> Result<(), Box<Error>> {
+        let recorded = match self.header.setup {
+            Some(ref setup) => setup,
+            // nothing to check against
+            None => return Ok(()),
+        };
+        let differences = recorded.differences(&try! { ReplaySetup::current(data.config) });
+        if differences.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("It was recorded with other {}.", differences.join(", ")).into())
+        }
+    }
+
+    /// Starts the game the replay was recorded from, before any action.
+    pub fn start(&self, data: &GameData) -> World {
+        // replays are never saved, the mode makes no difference
+        World::new(self.header.seed, GameMode::Explore, data)
+    }
+
+    /// Plays the whole replay without showing anything, and returns the game as it ended.
+    pub fn run(&self, data: &GameData) -> World {
+        let mut world = self.start(data);
+        for action in &self.actions {
+            world.act(*action, data);
+        }
+        world
+    }
+}
+
+/// Writes the replay of a game while it is played, one action at a time.
+pub struct Recorder {
+    file: File,
+}
+
+impl Recorder {
+    /// Starts the replay of a new game, replacing whatever was at the path.
+    pub fn create<P: AsRef<Path>>(path: P, header: &ReplayHeader) -> Result<Self, Box<Error>> {
+        let mut file = try! { File::create(path) };
+        try! { writeln!(file, "{}", try! { json::encode(header) }) };
+        Ok(Recorder { file: file })
+    }
+
+    /// Goes on with the replay of a saved game that took `actions` actions,
+    /// played on with `setup`. Anything recorded after the game was saved
+    /// never happened and is dropped.
+    pub fn resume<P: AsRef<Path>>(path: P, actions: u32, setup: &ReplaySetup) -> Result<Self, Box<Error>> {
+        let mut length = 0;
+        {
+            let mut reader = BufReader::new(try! { File::open(path.as_ref()) });
+            let mut line = String::new();
+            // the header, then the actions
+            for index in 0..actions + 1 {
+                line.clear();
+                let read = try! { reader.read_line(&mut line) };
+                if read == 0 || !line.ends_with('\n') {
+                    return Err("The replay is shorter than the saved game.".into());
+                }
+                if index == 0 {
+                    let header = try! { ReplayHeader::parse(&line) };
+                    let changed = header.setup.map_or(false, |recorded| !recorded.differences(setup).is_empty());
+                    if changed {
+                        return Err("The settings or data files changed since the game began.".into());
+                    }
+                }
+                length += read as u64;
+            }
+        }
+        let mut file = try! { OpenOptions::new().write(true).open(path) };
+        try! { file.set_len(length) };
+        try! { file.seek(SeekFrom::End(0)) };
+        Ok(Recorder { file: file })
+    }
+
+    pub fn record(&mut self, action: Action) -> io::Result<()> {
+        let line = json::encode(&action).expect("actions can always be encoded");
+        writeln!(self.file, "{}", line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use rustc_serialize::json;
+    use world::Action::*;
+    use super::*;
+
+    /// Everything saved of a game, to compare two of them.
+    fn state(world: &World) -> String {
+        json::encode(&(&world.object_manager.objects, &world.game)).unwrap()
+    }
+
+    #[test]
+    fn replaying_the_actions_gives_the_same_game() {
+        let data = GameData::load(Config::default()).unwrap();
+        let path = env::temp_dir().join("replaying_the_actions_gives_the_same_game.replay");
+        let moves = [Move(1, 0), Move(1, 1), Move(0, 1), Wait, Move(-1, 1), Move(-1, 0), PickUp, Move(-1, -1),
+                     Move(0, -1), Move(1, -1), Descend];
+        let mut world = World::new(42, GameMode::Explore, &data);
+        let setup = ReplaySetup::current(world.config).unwrap();
+        let header = ReplayHeader { seed: world.game.seed, setup: Some(setup) };
+        world.recorder = Some(Recorder::create(&path, &header).unwrap());
+        for action in moves.iter().cycle().take(300) {
+            world.act(*action, &data);
+        }
+        world.recorder = None;
+
+        let replay = Replay::load(&path).unwrap();
+        assert!(replay.check(&data).is_ok());
+        assert_eq!(replay.actions.len(), world.game.actions_taken as usize);
+        assert_eq!(state(&replay.run(&data)), state(&world));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn resuming_drops_the_actions_after_the_save() {
+        let path = env::temp_dir().join("resuming_drops_the_actions_after_the_save.replay");
+        let setup = ReplaySetup::current(Config::default()).unwrap();
+        {
+            let header = ReplayHeader { seed: 7, setup: Some(setup.clone()) };
+            let mut recorder = Recorder::create(&path, &header).unwrap();
+            for _ in 0..5 {
+                recorder.record(Wait).unwrap();
+            }
+        }
+        {
+            let mut recorder = Recorder::resume(&path, 3, &setup).unwrap();
+            recorder.record(PickUp).unwrap();
+        }
+        let replay = Replay::load(&path).unwrap();
+        assert_eq!(replay.header.seed, 7);
+        assert_eq!(replay.actions, vec![Wait, Wait, Wait, PickUp]);
+        assert!(Recorder::resume(&path, 5, &setup).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replays_only_play_with_the_same_setup() {
+        let data = GameData::load(Config::default()).unwrap();
+        let setup = ReplaySetup::current(Config::default()).unwrap();
+        let mut other = setup.clone();
+        other.settings.map_width += 1;
+        other.settings.torch_radius += 1;
+        other.data_files[0].1 += 1;
+        assert_eq!(setup.differences(&setup), Vec::<String>::new());
+        assert_eq!(other.differences(&setup), vec!["map_width".to_string(), "torch_radius".to_string(),
+                                                   MONSTERS_FILE.to_string()]);
+
+        let replay = Replay { header: ReplayHeader { seed: 1, setup: Some(other) }, actions: vec![] };
+        assert!(replay.check(&data).is_err());
+        // older replays don't say, they are played anyway
+        let replay = Replay { header: ReplayHeader { seed: 1, setup: None }, actions: vec![] };
+        assert!(replay.check(&data).is_ok());
+    }
+}
@@ -0,0 +1,664 @@
+use std::cell::RefCell;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+use rustc_serialize::Decodable;
+use rustc_serialize::json::{self, Json};
+use tcod::colors;
+
+use config::*;
+use object::Object;
+use game::Game;
+use world::{World, Action};
+use messages::MessageLog;
+use replay::{Replay, ReplayHeader, ReplaySetup, Recorder};
+
+/// Version of the save file layout. Bump it whenever a change to the saved
+/// types would break older saves, and add a migration from the old version.
+pub const SAVE_FORMAT_VERSION: u32 = 7;
+
+/// Written at the start of every save file.
+#[derive(Debug, RustcEncodable, RustcDecodable)]
+pub struct SaveHeader {
+    pub format_version: u32,
+    // version of the game that wrote the save
+    pub game_version: String,
+    // seconds since the Unix epoch
+    pub timestamp: u64,
+    // shown in the list of saves
+    pub character_level: i32,
+    pub dungeon_level: u32,
+}
+
+#[derive(RustcEncodable)]
+struct SaveFile<'a> {
+    header: SaveHeader,
+    objects: &'a [RefCell<Object>],
+    game: &'a Game,
+}
+
+#[derive(RustcDecodable)]
+struct SavedGame {
+    objects: Vec<Object>,
+    game: Game,
+}
+
+/// Why a saved game could not be loaded.
+#[derive(Debug)]
+pub enum LoadError {
+    Missing,
+    Corrupt(String),
+    // written by a newer version of the game, in a format this one doesn't know
+    Incompatible { format_version: u32, game_version: String },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::Missing => write!(f, "No saved game to load."),
+            LoadError::Corrupt(ref reason) => {
+                write!(f, "The saved game is damaged and cannot be loaded.\n\n{}", reason)
+            },
+            LoadError::Incompatible { format_version, ref game_version } => {
+                write!(f, "The saved game was made by version {} of the game (save format {}), \
+                           which this version cannot read.", game_version, format_version)
+            },
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn description(&self) -> &str {
+        match *self {
+            LoadError::Missing => "missing save",
+            LoadError::Corrupt(_) => "corrupt save",
+            LoadError::Incompatible { .. } => "incompatible save",
+        }
+    }
+}
+
+/// Where the saves go: `$XDG_DATA_HOME/rusty_roguelike`, by default
+/// `~/.local/share/rusty_roguelike`, or the current directory without a home.
+pub fn save_dir() -> PathBuf {
+    match (env::var("XDG_DATA_HOME"), env::var("HOME")) {
+        (Ok(ref data_home), _) if !data_home.is_empty() => PathBuf::from(data_home).join(SAVE_DIR_NAME),
+        (_, Ok(home)) => PathBuf::from(home).join(".local").join("share").join(SAVE_DIR_NAME),
+        _ => PathBuf::from("."),
+    }
+}
+
+fn slot_path(slot: usize) -> PathBuf {
+    save_dir().join(format!("slot{}.save", slot + 1))
+}
+
+/// The replay of the game in a slot, written as the game is played.
+pub fn replay_path(slot: usize) -> PathBuf {
+    save_dir().join(format!("slot{}.replay", slot + 1))
+}
+
+/// Where the replay of the last game played is copied when leaving it.
+pub fn last_replay_path() -> PathBuf {
+    save_dir().join(REPLAY_FILE)
+}
+
+/// Starts recording the replay of a new game in a slot, once it was saved there.
+pub fn start_replay(world: &mut World, slot: usize) {
+    let recorder = ReplaySetup::current(world.config).and_then(|setup| {
+        Recorder::create(replay_path(slot), &ReplayHeader { seed: world.game.seed, setup: Some(setup) })
+    });
+    match recorder {
+        Ok(recorder) => world.recorder = Some(recorder),
+        Err(_e) => world.game.log.add("Could not record the replay of this game.", colors::RED),
+    }
+}
+
+pub fn save_game(world: &World, slot: usize) -> Result<(), Box<Error>> {
+    let (character_level, dungeon_level) = {
+        let player = world.object_manager.objects[PLAYER].borrow();
+        (player.level, world.game.dungeon_level)
+    };
+    let save_file = SaveFile {
+        header: SaveHeader {
+            format_version: SAVE_FORMAT_VERSION,
+            game_version: env!("CARGO_PKG_VERSION").into(),
+            timestamp: now(),
+            character_level: character_level,
+            dungeon_level: dungeon_level,
+        },
+        objects: &world.object_manager.objects,
+        game: &world.game,
+    };
+    let save_data = try! { json::encode(&save_file) };
+    try! { fs::create_dir_all(save_dir()) };
+    // written next to the slot first, so a crash while saving leaves the old save
+    let path = slot_path(slot);
+    let temp_path = path.with_extension("tmp");
+    {
+        let mut file = try! { File::create(&temp_path) };
+        try! { file.write_all(save_data.as_bytes()) };
+        try! { file.sync_all() };
+    }
+    try! { fs::rename(&temp_path, &path) };
+    Ok(())
+}
+
+/// Loads the game in a slot, to be played with the given settings, and goes
+/// on recording its replay from where it was saved.
+pub fn load_game(slot: usize, config: Config) -> Result<World, LoadError> {
+    let save = try! { read_save_file(slot) };
+    // version 6 saves and older hold the actions of the replay themselves
+    let old_actions = save.find_path(&["game", "actions"]).cloned();
+    let save = try! { migrate(save) };
+    let saved = try! {
+        SavedGame::decode(&mut json::Decoder::new(save)).map_err(|e| LoadError::Corrupt(e.to_string()))
+    };
+    let mut world = World::from_saved(saved.objects, saved.game, config);
+
+    if let Some(actions) = old_actions {
+        let actions = try! {
+            Vec::<Action>::decode(&mut json::Decoder::new(actions)).map_err(|e| LoadError::Corrupt(e.to_string()))
+        };
+        // these games were played with the settings and data files of their time,
+        // which are likely the ones at hand
+        let header = ReplayHeader { seed: world.game.seed, setup: ReplaySetup::current(config).ok() };
+        let replay = Replay { header: header, actions: actions };
+        // when this fails there is no replay to resume, which is reported below
+        let _ = replay.save(replay_path(slot));
+    }
+    let recorder = ReplaySetup::current(config).and_then(|setup| {
+        Recorder::resume(replay_path(slot), world.game.actions_taken, &setup)
+    });
+    match recorder {
+        Ok(recorder) => world.recorder = Some(recorder),
+        Err(_e) => world.game.log.add("The replay of this game can't go on, the rest won't be recorded.", colors::RED),
+    }
+    Ok(world)
+}
+
+pub fn slot_used(slot: usize) -> bool {
+    fs::metadata(slot_path(slot)).is_ok()
+}
+
+pub fn delete_save(slot: usize) -> Result<(), Box<Error>> {
+    try! { fs::remove_file(slot_path(slot)) };
+    Ok(())
+}
+
+/// What is in each save slot: None for an empty one, or the header of the save.
+pub fn list_saves() -> Vec<Option<Result<SaveHeader, LoadError>>> {
+    (0..SAVE_SLOTS).map(|slot| {
+        match read_save(slot) {
+            Ok(save) => Some(read_header(&save)),
+            Err(LoadError::Missing) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }).collect()
+}
+
+/// Saves used to go to a single file in the current directory. Moves it into
+/// the first slot if that one is free, so the game it holds can be continued.
+pub fn import_old_save() -> Result<(), Box<Error>> {
+    if fs::metadata(OLD_SAVE_FILE).is_err() || slot_used(0) {
+        return Ok(());
+    }
+    try! { fs::create_dir_all(save_dir()) };
+    try! { fs::copy(OLD_SAVE_FILE, slot_path(0)) };
+    try! { fs::remove_file(OLD_SAVE_FILE) };
+    Ok(())
+}
+
+/// Reads a save and brings it up to the current format.
+fn read_save(slot: usize) -> Result<Json, LoadError> {
+    let save = try! { read_save_file(slot) };
+    migrate(save)
+}
+
+fn read_save_file(slot: usize) -> Result<Json, LoadError> {
+    let mut json_save_state = String::new();
+    let mut file = match File::open(slot_path(slot)) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Err(LoadError::Missing),
+        Err(e) => return Err(LoadError::Corrupt(e.to_string())),
+    };
+    try! { file.read_to_string(&mut json_save_state).map_err(|e| LoadError::Corrupt(e.to_string())) };
+    Json::from_str(&json_save_state).map_err(|e| LoadError::Corrupt(e.to_string()))
+}
+
+/// Brings a save up to the current format.
+fn migrate(mut save: Json) -> Result<Json, LoadError> {
+    let format_version = try! { read_format_version(&save) };
+    if format_version > SAVE_FORMAT_VERSION {
+        let game_version = save.find_path(&["header", "game_version"])
+            .and_then(|version| version.as_string())
+            .unwrap_or("unknown")
+            .into();
+        return Err(LoadError::Incompatible {
+            format_version: format_version,
+            game_version: game_version,
+        });
+    }
+    for version in format_version..SAVE_FORMAT_VERSION {
+        save = try! { migration(version)(save).map_err(LoadError::Corrupt) };
+    }
+    Ok(save)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0)
+}
+
+/// The save format version. Saves from before there was a header are version 0.
+fn read_format_version(save: &Json) -> Result<u32, LoadError> {
+    if save.is_array() {
+        return Ok(0);
+    }
+    save.find_path(&["header", "format_version"])
+        .and_then(|version| version.as_u64())
+        .map(|version| version as u32)
+        .ok_or(LoadError::Corrupt("The save has no header.".into()))
+}
+
+fn read_header(save: &Json) -> Result<SaveHeader, LoadError> {
+    match save.find("header") {
+        Some(header) => {
+            SaveHeader::decode(&mut json::Decoder::new(header.clone()))
+                .map_err(|e| LoadError::Corrupt(format!("Bad save header: {}", e)))
+        },
+        None => Err(LoadError::Corrupt("The save has no header.".into())),
+    }
+}
+
+/// Formats a save time as "YYYY-MM-DD HH:MM", in UTC.
+pub fn format_time(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let seconds = timestamp % 86400;
+    // civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z / 146097;
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, seconds / 3600, seconds % 3600 / 60)
+}
+
+/// The function turning a save of the given format version into the next one.
+fn migration(version: u32) -> fn(Json) -> Result<Json, String> {
+    match version {
+        0 => migrate_v0,
+        1 => migrate_v1,
+        2 => migrate_v2,
+        3 => migrate_v3,
+        4 => migrate_v4,
+        5 => migrate_v5,
+        6 => migrate_v6,
+        _ => unreachable!(),
+    }
+}
+
+/// Version 0 saves are a bare `[objects, game]` pair. Older ones also miss
+/// the fields added since, which get their default values.
+fn migrate_v0(save: Json) -> Result<Json, String> {
+    let (mut objects, mut game) = match save {
+        Json::Array(mut parts) => {
+            if parts.len() != 2 {
+                return Err("Expected the objects and the game.".into());
+            }
+            let game = parts.pop().unwrap();
+            let objects = parts.pop().unwrap();
+            (objects, game)
+        },
+        _ => return Err("Expected the objects and the game.".into()),
+    };
+
+    for object in try! { objects.as_array_mut().ok_or("The objects are not a list.") } {
+        try! { migrate_v0_object(object) };
+    }
+
+    {
+        let game_fields = try! { game.as_object_mut().ok_or("The game is not an object.") };
+        // the dungeon was random before it had a seed, any seed will do for the levels to come
+        set_default(game_fields, "seed", Json::U64(0));
+        set_default(game_fields, "turn", Json::U64(0));
+        set_default(game_fields, "levels", Json::Array(vec![]));
+        set_default(game_fields, "actions", Json::Array(vec![]));
+
+        if let Some(&mut Json::Array(ref mut inventory)) = game_fields.get_mut("inventory") {
+            for item in inventory {
+                try! { migrate_v0_object(item) };
+            }
+        }
+    }
+
+    let mut header = json::Object::new();
+    header.insert("format_version".into(), Json::U64(1));
+    header.insert("game_version".into(), Json::String("unknown".into()));
+    header.insert("timestamp".into(), Json::U64(0));
+
+    let mut save = json::Object::new();
+    save.insert("header".into(), Json::Object(header));
+    save.insert("objects".into(), objects);
+    save.insert("game".into(), game);
+    Ok(Json::Object(save))
+}
+
+fn migrate_v0_object(object: &mut Json) -> Result<(), String> {
+    let fields = try! { object.as_object_mut().ok_or("An object is not an object.") };
+    set_default(fields, "effects", Json::Array(vec![]));
+
+    if let Some(&mut Json::Object(ref mut fighter)) = fields.get_mut("fighter") {
+        set_default(fighter, "base_speed", Json::I64(NORMAL_SPEED as i64));
+        set_default(fighter, "energy", Json::I64(ACTION_ENERGY as i64));
+    }
+    if let Some(&mut Json::Object(ref mut equipment)) = fields.get_mut("equipment") {
+        set_default(equipment, "speed_bonus", Json::I64(0));
+    }
+
+    // swords and shields used to be items of their own, they are all equipment now
+    let is_old_equipment = match fields.get("item") {
+        Some(&Json::String(ref item)) => item == "Sword" || item == "Shield",
+        _ => false,
+    };
+    if is_old_equipment {
+        fields.insert("item".into(), Json::String("Equipment".into()));
+    }
+
+    // confusion used to be an AI wrapping the previous one, it's an effect now.
+    // Confusing a confused monster wrapped it again, the turns add up
+    let mut confused_turns = None;
+    loop {
+        let (previous_ai, num_turns) = match fields.get("ai") {
+            Some(ai) if ai.find("variant").and_then(|v| v.as_string()) == Some("Confused") => {
+                let ai_fields = try! { ai.find("fields").and_then(|f| f.as_array()).ok_or("Bad confused AI.") };
+                if ai_fields.len() != 2 {
+                    return Err("Bad confused AI.".into());
+                }
+                (ai_fields[0].clone(), ai_fields[1].as_i64().unwrap_or(0))
+            },
+            _ => break,
+        };
+        fields.insert("ai".into(), previous_ai);
+        confused_turns = Some(confused_turns.unwrap_or(0) + num_turns);
+    }
+    if let Some(turns) = confused_turns {
+        let mut effect = json::Object::new();
+        effect.insert("kind".into(), Json::String("Confusion".into()));
+        effect.insert("turns".into(), Json::I64(turns));
+        effect.insert("power".into(), Json::I64(0));
+        fields.insert("effects".into(), Json::Array(vec![Json::Object(effect)]));
+    }
+    Ok(())
+}
+
+/// Version 1 headers don't have the summary shown in the list of saves,
+/// it's taken from the saved game.
+fn migrate_v1(mut save: Json) -> Result<Json, String> {
+    let character_level = save.find("objects")
+        .and_then(|objects| objects.as_array())
+        .and_then(|objects| objects.get(PLAYER))
+        .and_then(|player| player.find("level"))
+        .and_then(|level| level.as_i64())
+        .unwrap_or(1);
+    let dungeon_level = save.find_path(&["game", "dungeon_level"])
+        .and_then(|level| level.as_u64())
+        .unwrap_or(1);
+
+    {
+        let header = try! { part_mut(&mut save, "header") };
+        header.insert("format_version".into(), Json::U64(2));
+        header.insert("character_level".into(), Json::I64(character_level));
+        header.insert("dungeon_level".into(), Json::U64(dungeon_level));
+    }
+    Ok(save)
+}
+
+/// Version 2 games don't have a mode. They could always be loaded again
+/// after the player died, as in explore mode.
+fn migrate_v2(mut save: Json) -> Result<Json, String> {
+    {
+        let game = try! { part_mut(&mut save, "game") };
+        set_default(game, "mode", Json::String("Explore".into()));
+    }
+    {
+        let header = try! { part_mut(&mut save, "header") };
+        header.insert("format_version".into(), Json::U64(3));
+    }
+    Ok(save)
+}
+
+/// Version 3 logs are a list of `[text, color]` pairs that grows forever.
+/// Only the last messages are kept, the turn they were said on is unknown.
+fn migrate_v3(mut save: Json) -> Result<Json, String> {
+    {
+        let game = try! { part_mut(&mut save, "game") };
+        let turn = game.get("turn").and_then(|turn| turn.as_u64()).unwrap_or(0);
+        let old_log = match game.remove("log") {
+            Some(Json::Array(log)) => log,
+            _ => return Err("The message log is not a list.".into()),
+        };
+
+        let skip = old_log.len().saturating_sub(MSG_HISTORY);
+        let mut messages = vec![];
+        for entry in old_log.into_iter().skip(skip) {
+            let (text, color) = match entry {
+                Json::Array(mut pair) => {
+                    if pair.len() != 2 {
+                        return Err("A message is not a text and a color.".into());
+                    }
+                    let color = pair.pop().unwrap();
+                    let text = pair.pop().unwrap();
+                    (text, color)
+                },
+                _ => return Err("A message is not a text and a color.".into()),
+            };
+            let mut message = json::Object::new();
+            message.insert("text".into(), text);
+            message.insert("color".into(), color);
+            message.insert("turn".into(), Json::U64(0));
+            message.insert("count".into(), Json::U64(1));
+            messages.push(Json::Object(message));
+        }
+
+        let mut log = json::Object::new();
+        log.insert("messages".into(), Json::Array(messages));
+        log.insert("turn".into(), Json::U64(turn));
+        game.insert("log".into(), Json::Object(log));
+    }
+    {
+        let header = try! { part_mut(&mut save, "header") };
+        header.insert("format_version".into(), Json::U64(4));
+    }
+    Ok(save)
+}
+
+/// Version 4 objects don't tell the items the player dropped, auto-explore
+/// may pick them up again.
+fn migrate_v4(mut save: Json) -> Result<Json, String> {
+    try! { migrate_objects(&mut save, |fields| set_default(fields, "dropped", Json::Boolean(false))) };
+    {
+        let header = try! { part_mut(&mut save, "header") };
+        header.insert("format_version".into(), Json::U64(5));
+    }
+    Ok(save)
+}
+
+/// Version 5 fighters don't heal by themselves, they start from nothing.
+fn migrate_v5(mut save: Json) -> Result<Json, String> {
+    try! { migrate_objects(&mut save, |fields| {
+        if let Some(&mut Json::Object(ref mut fighter)) = fields.get_mut("fighter") {
+            set_default(fighter, "regen", Json::I64(0));
+        }
+    }) };
+    {
+        let header = try! { part_mut(&mut save, "header") };
+        header.insert("format_version".into(), Json::U64(6));
+    }
+    Ok(save)
+}
+
+/// Version 6 games keep every action of the player, to replay them. They
+/// are written to a replay file as the game is played now, the save only
+/// counts them. Loading the game moves the old actions to the replay file.
+fn migrate_v6(mut save: Json) -> Result<Json, String> {
+    {
+        let game = try! { part_mut(&mut save, "game") };
+        let actions_taken = match game.remove("actions") {
+            Some(Json::Array(actions)) => actions.len(),
+            _ => return Err("The actions are not a list.".into()),
+        };
+        game.insert("actions_taken".into(), Json::U64(actions_taken as u64));
+    }
+    {
+        let header = try! { part_mut(&mut save, "header") };
+        header.insert("format_version".into(), Json::U64(7));
+    }
+    Ok(save)
+}
+
+/// Changes every object of a save: the ones on the current level, in the
+/// inventory and on the other levels.
+fn migrate_objects<F>(save: &mut Json, migrate: F) -> Result<(), String>
+    where F: Fn(&mut json::Object)
+{
+    let each_object = |objects: &mut [Json]| -> Result<(), String> {
+        for object in objects {
+            migrate(try! { object.as_object_mut().ok_or("An object is not an object.") });
+        }
+        Ok(())
+    };
+
+    {
+        let parts = try! { save.as_object_mut().ok_or("The save is not an object.") };
+        if let Some(&mut Json::Array(ref mut objects)) = parts.get_mut("objects") {
+            try! { each_object(objects) };
+        }
+    }
+    let game = try! { part_mut(save, "game") };
+    if let Some(&mut Json::Array(ref mut inventory)) = game.get_mut("inventory") {
+        try! { each_object(inventory) };
+    }
+    if let Some(&mut Json::Array(ref mut levels)) = game.get_mut("levels") {
+        // the levels never visited and the current one are null
+        for level in levels.iter_mut().filter_map(|level| level.as_object_mut()) {
+            if let Some(&mut Json::Array(ref mut objects)) = level.get_mut("objects") {
+                try! { each_object(objects) };
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The header or the game of a save that has one.
+fn part_mut<'a>(save: &'a mut Json, name: &str) -> Result<&'a mut json::Object, String> {
+    save.as_object_mut()
+        .and_then(|parts| parts.get_mut(name))
+        .and_then(|part| part.as_object_mut())
+        .ok_or(format!("The save has no {}.", name))
+}
+
+fn set_default(fields: &mut json::Object, name: &str, value: Json) {
+    if !fields.contains_key(name) {
+        fields.insert(name.into(), value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object::{Ai, Item};
+    use game::GameMode;
+
+    fn object(name: &str, rest: &str) -> String {
+        format!(r#"{{"x": 1, "y": 2, "char": "@", "color": {{"r": 1, "g": 2, "b": 3}}, "name": "{}",
+                     "blocks": true, "alive": true, "always_visible": false, "level": 1, {}}}"#, name, rest)
+    }
+
+    /// A save from before saves had a header: a bare `[objects, game]` pair
+    /// with a confused orc, a dagger that is still a sword and a log of pairs.
+    fn version_0_save() -> Json {
+        let player = object("player", r#""fighter": {"base_max_hp": 100, "hp": 50, "base_defense": 1, "base_power": 2,
+                                                    "xp": 0, "on_death": "Player"},
+                                       "ai": null, "item": null, "equipment": null"#);
+        let orc = object("orc", r#""fighter": {"base_max_hp": 10, "hp": 10, "base_defense": 0, "base_power": 3,
+                                              "xp": 35, "on_death": "Monster"},
+                                   "ai": {"variant": "Confused", "fields": [{"variant": "Confused", "fields": ["Basic", 2]}, 5]},
+                                   "item": null, "equipment": null"#);
+        let dagger = object("dagger", r#""fighter": null, "ai": null, "item": "Sword",
+                                         "equipment": {"slot": "LeftHand", "equipped": true, "max_hp_bonus": 0,
+                                                       "power_bonus": 2, "defense_bonus": 0}"#);
+        let game = format!(r#"{{"map": [[{{"blocked": true, "block_sight": true, "explored": false}}]],
+                                "log": [["Welcome!", {{"r": 1, "g": 2, "b": 3}}]],
+                                "inventory": [{}], "dungeon_level": 1}}"#, dagger);
+        Json::from_str(&format!("[[{}, {}], {}]", player, orc, game)).unwrap()
+    }
+
+    #[test]
+    fn migrates_version_0_to_the_current_format() {
+        let save = version_0_save();
+        assert_eq!(read_format_version(&save).unwrap(), 0);
+        let save = migrate(save).unwrap();
+
+        let header = read_header(&save).unwrap();
+        assert_eq!(header.format_version, SAVE_FORMAT_VERSION);
+        assert_eq!(header.character_level, 1);
+        assert_eq!(header.dungeon_level, 1);
+
+        let saved = SavedGame::decode(&mut json::Decoder::new(save)).unwrap();
+        let (player, orc) = (&saved.objects[0], &saved.objects[1]);
+        assert_eq!(player.fighter.unwrap().regen, 0);
+        assert_eq!(orc.ai, Some(Ai::Basic));
+        assert_eq!(orc.effects.len(), 1);
+        assert_eq!(orc.effects[0].turns, 7);
+        assert_eq!(saved.game.inventory[0].item, Some(Item::Equipment));
+        assert!(!saved.game.inventory[0].dropped);
+        assert_eq!(saved.game.log.iter().map(|message| message.text.clone()).collect::<Vec<_>>(), vec!["Welcome!"]);
+        assert_eq!(saved.game.mode, GameMode::Explore);
+        assert_eq!(saved.game.actions_taken, 0);
+    }
+
+    #[test]
+    fn migration_counts_the_actions_left_to_the_replay() {
+        let save = Json::from_str(r#"{"header": {"format_version": 6},
+                                      "game": {"actions": ["Wait", "PickUp", {"variant": "Move", "fields": [1, 0]}]}}"#);
+        let save = migrate_v6(save.unwrap()).unwrap();
+        assert_eq!(read_format_version(&save).unwrap(), 7);
+        assert!(save.find_path(&["game", "actions"]).is_none());
+        assert_eq!(save.find_path(&["game", "actions_taken"]).and_then(|n| n.as_u64()), Some(3));
+    }
+
+    #[test]
+    fn newer_saves_are_incompatible() {
+        let save = Json::from_str(r#"{"header": {"format_version": 1000, "game_version": "9.0.0"}}"#).unwrap();
+        match migrate(save) {
+            Err(LoadError::Incompatible { format_version, game_version }) => {
+                assert_eq!(format_version, 1000);
+                assert_eq!(game_version, "9.0.0");
+            },
+            other => panic!("expected an incompatible save, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn saves_without_header_are_corrupt() {
+        let save = Json::from_str(r#"{"game": {}}"#).unwrap();
+        match read_format_version(&save) {
+            Err(LoadError::Corrupt(_)) => {},
+            other => panic!("expected a corrupt save, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn formats_times_in_utc() {
+        assert_eq!(format_time(0), "1970-01-01 00:00");
+        assert_eq!(format_time(951782400), "2000-02-29 00:00");
+        assert_eq!(format_time(1709251199), "2024-02-29 23:59");
+        assert_eq!(format_time(1735689600), "2025-01-01 00:00");
+    }
+}
@@ -0,0 +1,465 @@
+use std::io::{self, Read, Write};
+use std::error::Error;
+use std::process::{Command, Stdio};
+
+use tcod::colors::{self, Color};
+use tcod::input::{Key, KeyCode};
+
+use config::*;
+use world::World;
+use camera::Camera;
+use frontend::{self, Frontend, CursorInput};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Cell {
+    char: char,
+    fg: Color,
+    bg: Color,
+}
+
+const BLANK: Cell = Cell { char: ' ', fg: colors::WHITE, bg: colors::BLACK };
+
+/// Plays the game in a text terminal, drawing with ANSI escape codes.
+/// The screen is kept as a grid of cells, and only the cells that changed
+/// since the last flush are sent to the terminal.
+pub struct Terminal {
+    cells: Vec<Cell>,
+    shown: Vec<Option<Cell>>,
+    camera: Camera,
+    config: Config,
+    // terminal settings to put back when leaving
+    saved_mode: String,
+}
+
+impl Terminal {
+    /// Switches the terminal to raw mode: keys are read as soon as they are
+    /// pressed, without echo. It's switched back when the Terminal is dropped.
+    pub fn new(config: Config) -> Result<Self, Box<Error>> {
+        let saved_mode = try! { stty(&["-g"]) };
+        // reads wait for a tenth of a second at most, to tell Escape from escape sequences
+        try! { stty(&["raw", "-echo", "min", "0", "time", "1"]) };
+
+        let size = (config.screen_width * config.screen_height) as usize;
+        let terminal = Terminal {
+            cells: vec![BLANK; size],
+            shown: vec![None; size],
+            camera: Camera::new(config.camera_width(), config.camera_height()),
+            config: config,
+            saved_mode: saved_mode.trim().into(),
+        };
+        // hide the cursor and clear the screen
+        print!("\x1b[?25l\x1b[0m\x1b[2J");
+        try! { io::stdout().flush() };
+        Ok(terminal)
+    }
+
+    /// Index of the cell at a screen position, None if it's off the screen.
+    fn cell_index(&self, x: i32, y: i32) -> Option<usize> {
+        if x >= 0 && y >= 0 && x < self.config.screen_width && y < self.config.screen_height {
+            Some((y * self.config.screen_width + x) as usize)
+        } else {
+            None
+        }
+    }
+
+    fn put_char(&mut self, x: i32, y: i32, char: char, fg: Color) {
+        if let Some(index) = self.cell_index(x, y) {
+            let cell = &mut self.cells[index];
+            cell.char = char;
+            cell.fg = fg;
+        }
+    }
+
+    fn set_background(&mut self, x: i32, y: i32, bg: Color) {
+        if let Some(index) = self.cell_index(x, y) {
+            self.cells[index].bg = bg;
+        }
+    }
+
+    fn print(&mut self, x: i32, y: i32, text: &str, fg: Color) {
+        for (index, char) in text.chars().enumerate() {
+            self.put_char(x + index as i32, y, char, fg);
+        }
+    }
+
+    /// Blanks out a rectangle, like a window put over the screen.
+    fn fill(&mut self, x: i32, y: i32, width: i32, height: i32) {
+        for cy in y..(y + height) {
+            for cx in x..(x + width) {
+                self.put_char(cx, cy, ' ', colors::WHITE);
+                self.set_background(cx, cy, colors::BLACK);
+            }
+        }
+    }
+
+    /// Sends the changed cells to the terminal.
+    fn flush(&mut self) {
+        let mut out = String::new();
+        let mut cursor = None;
+        let mut colors = None;
+        for y in 0..self.config.screen_height {
+            for x in 0..self.config.screen_width {
+                let index = (y * self.config.screen_width + x) as usize;
+                let cell = self.cells[index];
+                if self.shown[index] == Some(cell) {
+                    continue;
+                }
+                if cursor != Some((x, y)) {
+                    out.push_str(&format!("\x1b[{};{}H", y + 1, x + 1));
+                }
+                if colors != Some((cell.fg, cell.bg)) {
+                    out.push_str(&format!("\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m",
+                                          cell.fg.r, cell.fg.g, cell.fg.b, cell.bg.r, cell.bg.g, cell.bg.b));
+                    colors = Some((cell.fg, cell.bg));
+                }
+                out.push(cell.char);
+                cursor = Some((x + 1, y));
+                self.shown[index] = Some(cell);
+            }
+        }
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(out.as_bytes());
+        let _ = stdout.flush();
+    }
+
+    /// Draws a window in the middle of the screen with the text in it,
+    /// and returns the line under the text.
+    fn window(&mut self, header: &str, lines: usize, width: i32) -> (i32, i32) {
+        let header_lines = if header.is_empty() { vec![] } else { frontend::wrap(header, width) };
+        let height = header_lines.len() as i32 + lines as i32;
+        let x = self.config.screen_width / 2 - width / 2;
+        let y = self.config.screen_height / 2 - height / 2;
+
+        self.fill(x, y, width, height);
+        for (index, line) in header_lines.iter().enumerate() {
+            self.print(x, y + index as i32, line, colors::WHITE);
+        }
+        (x, y + header_lines.len() as i32)
+    }
+
+    /// Waits for a key press.
+    fn wait_for_key(&mut self) -> Key {
+        loop {
+            if let Some(byte) = read_byte() {
+                return key_from_bytes(byte);
+            }
+        }
+    }
+
+    /// Draws the whole screen, flush only sends what changed.
+    fn draw(&mut self, world: &World) {
+        for cell in self.cells.iter_mut() {
+            *cell = BLANK;
+        }
+
+        {
+            let player = world.object_manager.objects[PLAYER].borrow();
+            self.camera.follow(player.x, player.y, &world.game.map);
+        }
+
+        // draw map
+        for screen_y in 0..self.config.camera_height() {
+            for screen_x in 0..self.config.camera_width() {
+                let (x, y) = match self.camera.to_map(screen_x, screen_y) {
+                    Some(pos) => pos,
+                    None => continue,
+                };
+                let tile = &world.game.map[x as usize][y as usize];
+                let visible = world.fov.is_in_fov(x, y);
+                let color = match (visible, tile.block_sight) {
+                    // outside fov
+                    (false, true) => self.config.color_dark_wall,
+                    (false, false) => self.config.color_dark_ground,
+                    // inside fov
+                    (true, true) => self.config.color_light_wall,
+                    (true, false) => self.config.color_light_ground,
+                };
+                // render only explored tiles
+                if tile.explored {
+                    self.set_background(screen_x, screen_y, color);
+                }
+            }
+        }
+
+        // draw objects, non-blocking ones first
+        let mut to_draw: Vec<_> = world.object_manager.objects.iter().map(|c| c.borrow()).filter(|o| {
+            world.fov.is_in_fov(o.x, o.y) || (o.always_visible && world.game.map[o.x as usize][o.y as usize].explored)
+        }).collect();
+        to_draw.sort_by(|o1, o2| { o1.blocks.cmp(&o2.blocks) });
+        for object in &to_draw {
+            if let Some((x, y)) = self.camera.to_screen(object.x, object.y) {
+                self.put_char(x, y, object.char, object.color);
+            }
+        }
+
+        self.draw_panel(world);
+    }
+
+    fn draw_panel(&mut self, world: &World) {
+        let game = &world.game;
+
+        let config = self.config;
+        let panel_y = config.panel_y();
+
+        // draw the messages
+        let mut y = panel_y + config.msg_height() as i32;
+        for message in game.log.iter().rev() {
+            let lines = frontend::wrap(&message.display(), config.msg_width());
+            y -= lines.len() as i32;
+            if y < panel_y {
+                break;
+            }
+            for (index, line) in lines.iter().enumerate() {
+                self.print(config.msg_x(), y + index as i32, line, message.color);
+            }
+        }
+
+        // draw stats and information
+        let player = world.object_manager.objects[PLAYER].borrow();
+        let hp = player.fighter.map_or(0, |f| f.hp);
+        let max_hp = player.max_hp(game);
+        let bar_width = (hp as f32 / max_hp as f32 * config.bar_width as f32) as i32;
+        for x in 0..config.bar_width {
+            let color = if x < bar_width { colors::LIGHT_RED } else { colors::DARKER_RED };
+            self.set_background(1 + x, panel_y + 1, color);
+        }
+        let text = format!("HP: {}/{}", hp, max_hp);
+        let text_x = 1 + config.bar_width / 2 - text.len() as i32 / 2;
+        self.print(text_x, panel_y + 1, &text, colors::WHITE);
+        self.print(1, panel_y + 3, &format!("Dungeon Level: {}", game.dungeon_level), colors::WHITE);
+
+        // status effects
+        for (index, effect) in player.effects.iter().enumerate() {
+            self.print(1 + 3 * index as i32, panel_y + 5, effect.kind.icon(), effect.kind.color());
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        // show the cursor again and leave a clean screen
+        print!("\x1b[0m\x1b[2J\x1b[H\x1b[?25h");
+        let _ = io::stdout().flush();
+        let _ = stty(&[self.saved_mode.as_ref()]);
+    }
+}
+
+impl Frontend for Terminal {
+    fn is_closed(&self) -> bool {
+        false
+    }
+
+    fn render(&mut self, world: &World, _map_changed: bool) {
+        self.draw(world);
+        self.flush();
+    }
+
+    fn check_for_key(&mut self) -> Key {
+        // turns only pass on key presses, so there's nothing to do until one comes
+        self.wait_for_key()
+    }
+
+    fn key_pressed(&mut self) -> bool {
+        // reads don't wait for long, see Terminal::new
+        read_byte().map(key_from_bytes).is_some()
+    }
+
+    fn clear(&mut self) {
+        for cell in self.cells.iter_mut() {
+            *cell = BLANK;
+        }
+    }
+
+    fn print_center(&mut self, y: i32, text: &str, color: Color) {
+        let x = self.config.screen_width / 2 - text.chars().count() as i32 / 2;
+        self.print(x, y, text, color);
+    }
+
+    fn menu(&mut self, header: &str, options: &[&str], width: i32) -> Option<usize> {
+        assert!(options.len() <= MAX_INVENTORY_SIZE as usize,
+            "Cannot have a menu with more than {} options", MAX_INVENTORY_SIZE);
+
+        let (x, y) = self.window(header, options.len(), width);
+        for (index, option_text) in options.iter().enumerate() {
+            let menu_letter = (b'a' + index as u8) as char;
+            let text: String = format!("({}) {}", menu_letter, option_text).chars().take(width as usize).collect();
+            self.print(x, y + index as i32, &text, colors::WHITE);
+        }
+        self.flush();
+        let key = self.wait_for_key();
+
+        // converts ASCII key to index (a is 0, b is 1, etc)
+        if key.printable.is_alphabetic() {
+            let index = key.printable.to_ascii_lowercase() as usize - 'a' as usize;
+            if index < options.len() {
+                Some(index)
+            } else {
+                None
+            }
+        } else {
+            None
+        }
+    }
+
+    fn msg(&mut self, text: &str, width: i32) {
+        self.window(text, 0, width);
+        self.flush();
+    }
+
+    fn prompt(&mut self, text: &str, width: i32) -> Key {
+        self.msg(text, width);
+        self.wait_for_key()
+    }
+
+    fn input_box(&mut self, header: &str, width: i32) -> Option<String> {
+        let mut text = String::new();
+
+        loop {
+            let (x, y) = self.window(header, 1, width);
+            self.print(x, y, &format!("> {}_", text), colors::WHITE);
+            self.flush();
+
+            let key = self.wait_for_key();
+            match key.code {
+                KeyCode::Enter => return Some(text),
+                KeyCode::Escape => return None,
+                KeyCode::Backspace => { text.pop(); },
+                _ => {
+                    if key.printable != '\0' && !key.printable.is_control() && (text.len() as i32) < width - 3 {
+                        text.push(key.printable);
+                    }
+                },
+            }
+        }
+    }
+
+    fn config(&self) -> &Config {
+        &self.config
+    }
+
+    fn show_page(&mut self, title: &str, lines: &[(String, Color)], footer: &str) -> Key {
+        self.clear();
+        self.print(1, 0, title, colors::LIGHT_YELLOW);
+        for (index, &(ref line, color)) in lines.iter().enumerate() {
+            self.print(1, 2 + index as i32, line, color);
+        }
+        self.print(1, self.config.screen_height - 1, footer, colors::LIGHT_GREY);
+        self.flush();
+        self.wait_for_key()
+    }
+
+    fn show_cursor(&mut self, world: &World, cursor: (i32, i32), highlights: &[((i32, i32), Color)],
+                   prompt: &str, description: &[(String, Color)]) -> CursorInput {
+        self.draw(world);
+        self.print(1, 0, prompt, colors::LIGHT_CYAN);
+
+        for &((x, y), color) in highlights {
+            if let Some((screen_x, screen_y)) = self.camera.to_screen(x, y) {
+                self.set_background(screen_x, screen_y, color);
+            }
+        }
+
+        // the panel goes on the side of the screen away from the cursor
+        let mut panel_x = self.config.screen_width - LOOK_WIDTH - 1;
+        if let Some((x, y)) = self.camera.to_screen(cursor.0, cursor.1) {
+            self.set_background(x, y, colors::WHITE);
+            if x >= self.config.camera_width() / 2 {
+                panel_x = 1;
+            }
+        }
+        self.fill(panel_x, 2, LOOK_WIDTH, description.len() as i32 + 2);
+        for (index, &(ref line, color)) in description.iter().enumerate() {
+            self.print(panel_x + 1, 3 + index as i32, line, color);
+        }
+
+        self.flush();
+        CursorInput::Key(self.wait_for_key())
+    }
+}
+
+/// Runs `stty` on the terminal the game is played in, returns what it printed.
+fn stty(args: &[&str]) -> Result<String, Box<Error>> {
+    let output = try! { Command::new("stty").args(args).stdin(Stdio::inherit()).output() };
+    if !output.status.success() {
+        return Err(From::from(String::from_utf8_lossy(&output.stderr).into_owned()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Reads a byte typed on the keyboard, None if nothing came for a moment.
+fn read_byte() -> Option<u8> {
+    let mut buffer = [0; 1];
+    match io::stdin().read(&mut buffer) {
+        Ok(1) => Some(buffer[0]),
+        _ => None,
+    }
+}
+
+/// Turns the bytes the terminal sends for a key, starting with `first`,
+/// into a key as tcod would report it.
+fn key_from_bytes(first: u8) -> Key {
+    let key = |code: KeyCode, printable: char| Key { code: code, printable: printable, pressed: true, ..Default::default() };
+
+    match first {
+        // escape alone, or the start of an escape sequence
+        27 => {
+            match read_byte() {
+                None => key(KeyCode::Escape, '\0'),
+                Some(b'[') | Some(b'O') => escape_sequence(),
+                Some(13) => Key { alt: true, ..key(KeyCode::Enter, '\0') },
+                Some(byte) => Key { alt: true, ..key_from_bytes(byte) },
+            }
+        },
+        13 | 10 => key(KeyCode::Enter, '\0'),
+        127 | 8 => key(KeyCode::Backspace, '\0'),
+        9 => key(KeyCode::Tab, '\0'),
+        // Ctrl-C leaves the game like Escape would, raw mode doesn't let it kill us
+        3 => key(KeyCode::Escape, '\0'),
+        // the numeric keypad sends digits, keep them as numpad keys so they move the player
+        b'1'..=b'9' => {
+            let codes = [KeyCode::NumPad1, KeyCode::NumPad2, KeyCode::NumPad3,
+                         KeyCode::NumPad4, KeyCode::NumPad5, KeyCode::NumPad6,
+                         KeyCode::NumPad7, KeyCode::NumPad8, KeyCode::NumPad9];
+            key(codes[(first - b'1') as usize], first as char)
+        },
+        32..=126 => {
+            if first == b' ' {
+                key(KeyCode::Spacebar, ' ')
+            } else {
+                key(KeyCode::Char, first as char)
+            }
+        },
+        _ => Default::default(),
+    }
+}
+
+/// Reads the rest of `ESC [`: arrows, home, end and page keys.
+fn escape_sequence() -> Key {
+    let key = |code: KeyCode| Key { code: code, pressed: true, ..Default::default() };
+
+    let mut number = String::new();
+    loop {
+        match read_byte() {
+            Some(byte) if byte.is_ascii_digit() || byte == b';' => number.push(byte as char),
+            Some(b'A') => return key(KeyCode::Up),
+            Some(b'B') => return key(KeyCode::Down),
+            Some(b'C') => return key(KeyCode::Right),
+            Some(b'D') => return key(KeyCode::Left),
+            Some(b'H') => return key(KeyCode::Home),
+            Some(b'F') => return key(KeyCode::End),
+            Some(b'E') => return key(KeyCode::NumPad5),
+            Some(b'~') => {
+                return match number.as_ref() {
+                    "1" | "7" => key(KeyCode::Home),
+                    "4" | "8" => key(KeyCode::End),
+                    "5" => key(KeyCode::PageUp),
+                    "6" => key(KeyCode::PageDown),
+                    "2" => key(KeyCode::Insert),
+                    "3" => key(KeyCode::Delete),
+                    _ => Default::default(),
+                }
+            },
+            _ => return Default::default(),
+        }
+    }
+}
@@ -0,0 +1,410 @@
+use tcod::console::*;
+use tcod::input::{self, Event, Key, Mouse};
+use tcod::colors::{self, Color};
+
+use config::*;
+use object::*;
+use world::World;
+use camera::Camera;
+use frontend::{Frontend, CursorInput};
+
+/// The tcod front-end: consoles to draw the world on, the mouse state
+/// and the settings giving the screen layout.
+pub struct Tcod {
+    pub root: Root,
+    pub con: Offscreen,
+    pub panel: Offscreen,
+    pub mouse: Mouse,
+    // map tile clicked during the last check_for_key
+    pub click: Option<(i32, i32)>,
+    pub camera: Camera,
+    pub config: Config,
+}
+
+/// Draws the world. The map itself is only drawn again when it changed,
+/// i.e. the player moved or its sight changed, or when the camera moved.
+pub fn render_all(tcod: &mut Tcod, world: &World, map_changed: bool)
+{
+    let game = &world.game;
+    let config = tcod.config;
+    let camera_moved = {
+        let player = world.object_manager.objects[PLAYER].borrow();
+        tcod.camera.follow(player.x, player.y, &game.map)
+    };
+
+    // draw map
+    if map_changed || camera_moved {
+        tcod.con.clear();
+
+        for screen_y in 0..config.camera_height() {
+            for screen_x in 0..config.camera_width() {
+                let (x, y) = match tcod.camera.to_map(screen_x, screen_y) {
+                    Some(pos) => pos,
+                    None => continue,
+                };
+                let wall = game.map[x as usize][y as usize].block_sight;
+                let visible = world.fov.is_in_fov(x, y);
+                let color = match (visible, wall) {
+                    // outside fov
+                    (false, true) => config.color_dark_wall,
+                    (false, false) => config.color_dark_ground,
+                    // inside fov
+                    (true, true) => config.color_light_wall,
+                    (true, false) => config.color_light_ground,
+                };
+
+                // render only explored tiles
+                if game.map[x as usize][y as usize].explored {
+                    tcod.con.set_char_background(screen_x, screen_y, color, BackgroundFlag::Set);
+                }
+            }
+        }
+    }
+
+    // draw objects
+    draw_objects(tcod, world);
+
+    // copy buffer
+    blit(&tcod.con, (0, 0), (config.camera_width(), config.camera_height()), &mut tcod.root, (0, 0), 1.0, 1.0);
+
+    // draw the gui panel
+    tcod.panel.set_default_background(colors::BLACK);
+    tcod.panel.clear();
+
+    // draw the messages
+    let mut y = config.msg_height() as i32;
+    for message in game.log.iter().rev() {
+        let msg = message.display();
+        let msg_height = tcod.panel.get_height_rect(config.msg_x(), y, config.msg_width(), 0, &msg);
+        y -= msg_height;
+        if y < 0 {
+            break;
+        }
+        tcod.panel.set_default_foreground(message.color);
+        tcod.panel.print_rect(config.msg_x(), y, config.msg_width(), 0, &msg);
+    }
+
+    // draw stats and information
+    {
+        let player = world.object_manager.objects[PLAYER].borrow();
+        let hp = player.fighter.map_or(0, |f| f.hp);
+        let max_hp = player.max_hp(game);
+        render_bar(&mut tcod.panel, 1, 1, config.bar_width, "HP", hp, max_hp, colors::LIGHT_RED, colors::DARKER_RED);
+        tcod.panel.print_ex(1, 3, BackgroundFlag::None, TextAlignment::Left, format!("Dungeon Level: {}", game.dungeon_level));
+
+        // status effects
+        for (index, effect) in player.effects.iter().enumerate() {
+            tcod.panel.set_default_foreground(effect.kind.color());
+            tcod.panel.print_ex(1 + 3 * index as i32, 5, BackgroundFlag::None, TextAlignment::Left, effect.kind.icon());
+        }
+    }
+    
+
+    // display names under mouse
+    tcod.panel.set_default_foreground(colors::LIGHT_GREY);
+    tcod.panel.print_ex(1, 0, BackgroundFlag::None, TextAlignment::Left, get_names_under_mouse(tcod.mouse, &tcod.camera, world));
+    blit(&tcod.panel, (0, 0), (config.screen_width, config.panel_height), &mut tcod.root, (0, config.panel_y()), 1.0, 1.0);
+}
+
+pub fn get_names_under_mouse(mouse: Mouse, camera: &Camera, world: &World) -> String {
+    let (x, y) = match camera.to_map(mouse.cx as i32, mouse.cy as i32) {
+        Some(pos) => pos,
+        None => return String::new(),
+    };
+
+    let names = world.object_manager.objects
+        .iter()
+        .map(|c| c.borrow())
+        .filter(|obj| {obj.pos() == (x, y) && world.fov.is_in_fov(obj.x, obj.y)})
+        .map(|obj| obj.name.clone())
+        .collect::<Vec<_>>();
+
+    names.join(", ")
+}
+
+pub fn render_bar(panel: &mut Offscreen, x: i32, y: i32, total_width: i32, name: &str, value: i32, maximum: i32,
+                bar_color: Color, back_color: Color) 
+{
+    let bar_width = (value as f32 / maximum as f32 * total_width as f32) as i32;
+    // background 
+    panel.set_default_background(back_color);
+    panel.rect(x, y, total_width, 1, false, BackgroundFlag::Screen);
+    // bar
+    panel.set_default_background(bar_color);
+    if bar_width > 0 {
+        panel.rect(x, y, bar_width, 1, false, BackgroundFlag::Screen);
+    }
+    // text on top
+    panel.set_default_foreground(colors::WHITE);
+    panel.print_ex(x + total_width / 2, y, BackgroundFlag::None, TextAlignment::Center, 
+        format!("{}: {}/{}", name, value, maximum));
+}
+
+pub fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, root: &mut Root) -> Option<usize> {
+    use std::ascii::AsciiExt;
+
+    assert!(options.len() <= MAX_INVENTORY_SIZE as usize, 
+        format!("Cannot have a menu with more than {} options", MAX_INVENTORY_SIZE));
+
+    // calculate height of the window
+    let header_height = if header.is_empty() {
+        0
+    } else {
+        root.get_height_rect(0, 0, width, root.height(), header)
+    };
+    let height = options.len() as i32 + header_height;
+
+    let mut window = Offscreen::new(width, height);
+
+    window.set_default_foreground(colors::WHITE);
+    window.print_rect_ex(0, 0, width, height, BackgroundFlag::None, TextAlignment::Left, header);
+
+    for (index, option_text) in options.iter().enumerate() {
+        let menu_letter = (b'a' + index as u8) as char;
+        let text = format!("({}) {}", menu_letter, option_text.as_ref());
+        window.print_ex(0, header_height + index as i32,  BackgroundFlag::None, TextAlignment::Left, text);
+    }
+
+    // "blit" to the center of root console
+    let x = root.width() / 2 - width / 2;
+    let y = root.height() / 2 - height / 2;
+    blit(&mut window, (0, 0), (width, height), root, (x, y), 1.0, 0.7);
+    // and show data immediately
+    root.flush();
+    let key = root.wait_for_keypress(true);
+
+    // converts ASCII key to index (a is 0, b is 1, etc)
+    if key.printable.is_alphabetic() {
+        let index = key.printable.to_ascii_lowercase() as usize - 'a' as usize;
+        if index < options.len() {
+            Some(index)
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
+
+// no waiting
+pub fn msg(text: &str, width: i32, root: &mut Root) {
+    let height = 1;
+    let mut window = Offscreen::new(width, height);
+    window.set_default_foreground(colors::WHITE);
+    window.print_rect_ex(0, 0, width, height, BackgroundFlag::None, TextAlignment::Left, text);
+    let x = root.width() / 2 - width / 2;
+    let y = root.height() / 2 - height / 2;
+    blit(&mut window, (0, 0), (width, height), root, (x, y), 1.0, 0.7);
+    root.flush();
+}
+
+/// Asks for a line of text, returns None if cancelled with Escape.
+pub fn input_box(header: &str, width: i32, root: &mut Root) -> Option<String> {
+    use tcod::input::KeyCode::{Enter, Escape, Backspace};
+
+    let header_height = root.get_height_rect(0, 0, width, root.height(), header);
+    let height = header_height + 1;
+    let mut text = String::new();
+
+    loop {
+        let mut window = Offscreen::new(width, height);
+        window.set_default_foreground(colors::WHITE);
+        window.print_rect_ex(0, 0, width, height, BackgroundFlag::None, TextAlignment::Left, header);
+        window.print_ex(0, header_height, BackgroundFlag::None, TextAlignment::Left, format!("> {}_", text));
+
+        let x = root.width() / 2 - width / 2;
+        let y = root.height() / 2 - height / 2;
+        blit(&mut window, (0, 0), (width, height), root, (x, y), 1.0, 0.7);
+        root.flush();
+
+        let key = root.wait_for_keypress(true);
+        match key.code {
+            Enter => return Some(text),
+            Escape => return None,
+            Backspace => { text.pop(); },
+            _ => {
+                if key.printable != '\0' && !key.printable.is_control() && (text.len() as i32) < width - 3 {
+                    text.push(key.printable);
+                }
+            },
+        }
+    }
+}
+
+fn draw_object(object: &Object, con: &mut Console, camera: &Camera) {
+    if let Some((x, y)) = camera.to_screen(object.x, object.y) {
+        con.set_default_foreground(object.color);
+        con.put_char(x, y, object.char, BackgroundFlag::None);
+    }
+}
+
+fn draw_objects(tcod: &mut Tcod, world: &World) {
+    let game = &world.game;
+    let mut to_draw: Vec<_> = world.object_manager.objects.iter().map(|c| c.borrow()).filter(|o| {
+        world.fov.is_in_fov(o.x, o.y) || (o.always_visible && game.map[o.x as usize][o.y as usize].explored)
+    }).collect();
+    // sort so that non-blocking objects come first
+    to_draw.sort_by(|o1, o2| { o1.blocks.cmp(&o2.blocks) });
+    for object in &to_draw {
+        draw_object(object, &mut tcod.con, &tcod.camera);
+    }
+}
+
+/// Erases the objects from where they were drawn, before they move.
+pub fn clear_objects(tcod: &mut Tcod, world: &World) {
+    for cell in &world.object_manager.objects {
+        let object = cell.borrow();
+        if let Some((x, y)) = tcod.camera.to_screen(object.x, object.y) {
+            tcod.con.put_char(x, y, ' ', BackgroundFlag::None);
+        }
+    }
+}
+
+/// Waits for a key or a mouse action while a cursor is shown.
+fn cursor_input(tcod: &mut Tcod, cursor: (i32, i32)) -> CursorInput {
+    loop {
+        tcod.root.flush();
+        if tcod.root.window_closed() {
+            return CursorInput::Cancel;
+        }
+        match input::check_for_event(input::KEY_PRESS | input::MOUSE).map(|e| e.1) {
+            Some(Event::Key(key)) => return CursorInput::Key(key),
+            Some(Event::Mouse(mouse)) => {
+                tcod.mouse = mouse;
+                if mouse.rbutton_pressed {
+                    return CursorInput::Cancel;
+                }
+                // the mouse is on the screen, the cursor is on the map
+                if let Some((x, y)) = tcod.camera.to_map(mouse.cx as i32, mouse.cy as i32) {
+                    if mouse.lbutton_pressed {
+                        return CursorInput::Click(x, y);
+                    }
+                    if (x, y) != cursor {
+                        return CursorInput::Hover(x, y);
+                    }
+                }
+            },
+            None => {},
+        }
+    }
+}
+
+impl Frontend for Tcod {
+    fn is_closed(&self) -> bool {
+        self.root.window_closed()
+    }
+
+    fn render(&mut self, world: &World, map_changed: bool) {
+        render_all(self, world, map_changed);
+        self.root.flush();
+        clear_objects(self, world);
+    }
+
+    fn check_for_key(&mut self) -> Key {
+        self.click = None;
+        match input::check_for_event(input::MOUSE | input::KEY_PRESS) {
+            Some((_, Event::Mouse(m))) => {
+                self.mouse = m;
+                if m.lbutton_pressed {
+                    self.click = self.camera.to_map(m.cx as i32, m.cy as i32);
+                }
+                Default::default()
+            },
+            Some((_, Event::Key(k))) => k,
+            _ => Default::default(),
+        }
+    }
+
+    fn key_pressed(&mut self) -> bool {
+        self.check_for_key().code != input::KeyCode::NoKey
+    }
+
+    fn take_click(&mut self) -> Option<(i32, i32)> {
+        self.click.take()
+    }
+
+    fn clear(&mut self) {
+        self.root.clear();
+    }
+
+    fn print_center(&mut self, y: i32, text: &str, color: Color) {
+        self.root.set_default_foreground(color);
+        self.root.print_ex(self.config.screen_width / 2, y, BackgroundFlag::None, TextAlignment::Center, text);
+    }
+
+    fn menu(&mut self, header: &str, options: &[&str], width: i32) -> Option<usize> {
+        menu(header, options, width, &mut self.root)
+    }
+
+    fn msg(&mut self, text: &str, width: i32) {
+        msg(text, width, &mut self.root);
+    }
+
+    fn prompt(&mut self, text: &str, width: i32) -> Key {
+        msg(text, width, &mut self.root);
+        self.root.wait_for_keypress(true)
+    }
+
+    fn input_box(&mut self, header: &str, width: i32) -> Option<String> {
+        input_box(header, width, &mut self.root)
+    }
+
+    fn show_page(&mut self, title: &str, lines: &[(String, Color)], footer: &str) -> Key {
+        self.root.set_default_background(colors::BLACK);
+        self.root.clear();
+        self.root.set_default_foreground(colors::LIGHT_YELLOW);
+        self.root.print_ex(1, 0, BackgroundFlag::None, TextAlignment::Left, title);
+        for (index, &(ref line, color)) in lines.iter().enumerate() {
+            self.root.set_default_foreground(color);
+            self.root.print_ex(1, 2 + index as i32, BackgroundFlag::None, TextAlignment::Left, line);
+        }
+        self.root.set_default_foreground(colors::LIGHT_GREY);
+        self.root.print_ex(1, self.config.screen_height - 1, BackgroundFlag::None, TextAlignment::Left, footer);
+        self.root.flush();
+        self.root.wait_for_keypress(true)
+    }
+
+    /// The mouse moves the cursor too, a left click picks the tile under it
+    /// and a right click cancels.
+    fn show_cursor(&mut self, world: &World, cursor: (i32, i32), highlights: &[((i32, i32), Color)],
+                   prompt: &str, description: &[(String, Color)]) -> CursorInput {
+        render_all(self, world, false);
+        self.root.set_default_foreground(colors::LIGHT_CYAN);
+        self.root.print_ex(1, 0, BackgroundFlag::None, TextAlignment::Left, prompt);
+
+        for &((x, y), color) in highlights {
+            if let Some((screen_x, screen_y)) = self.camera.to_screen(x, y) {
+                self.root.set_char_background(screen_x, screen_y, color, BackgroundFlag::Set);
+            }
+        }
+
+        // the panel goes on the side of the screen away from the cursor
+        let mut panel_x = self.config.screen_width - LOOK_WIDTH - 1;
+        if let Some((x, y)) = self.camera.to_screen(cursor.0, cursor.1) {
+            self.root.set_char_background(x, y, colors::WHITE, BackgroundFlag::Set);
+            if x >= self.config.camera_width() / 2 {
+                panel_x = 1;
+            }
+        }
+        let height = description.len() as i32 + 2;
+        let mut window = Offscreen::new(LOOK_WIDTH, height);
+        for (index, &(ref line, color)) in description.iter().enumerate() {
+            window.set_default_foreground(color);
+            window.print_ex(1, 1 + index as i32, BackgroundFlag::None, TextAlignment::Left, line);
+        }
+        blit(&window, (0, 0), (LOOK_WIDTH, height), &mut self.root, (panel_x, 2), 1.0, 0.7);
+
+        let input = cursor_input(self, cursor);
+        clear_objects(self, world);
+        input
+    }
+
+    fn config(&self) -> &Config {
+        &self.config
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        let fullscreen = self.root.is_fullscreen();
+        self.root.set_fullscreen(!fullscreen);
+    }
+}
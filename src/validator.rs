@@ -0,0 +1,108 @@
+use rand::StdRng;
+use std::cell::RefCell;
+
+use config::*;
+use map::*;
+use object::*;
+
+/// Ids of the objects that can't be walked to from where the player stands.
+pub fn unreachable_objects(map: &Map, objects: &[RefCell<Object>]) -> Vec<usize> {
+    let player_pos = objects[PLAYER].borrow().pos();
+    let reachable = reachable_tiles(map, player_pos);
+
+    objects.iter().enumerate()
+        .filter(|&(id, cell)| {
+            let (x, y) = cell.borrow().pos();
+            id != PLAYER && !reachable[x as usize][y as usize]
+        })
+        .map(|(id, _)| id)
+        .collect()
+}
+
+/// True when the stairs and every object can be reached by the player.
+pub fn is_valid(map: &Map, objects: &[RefCell<Object>]) -> bool {
+    unreachable_objects(map, objects).is_empty()
+}
+
+/// Digs a tunnel to every object that can't be reached, starting from the
+/// closest tile the player can already get to.
+pub fn repair(map: &mut Map, objects: &[RefCell<Object>], rng: &mut StdRng) {
+    let player_pos = objects[PLAYER].borrow().pos();
+
+    for id in unreachable_objects(map, objects) {
+        let target = objects[id].borrow().pos();
+        // an earlier tunnel may have opened this one too
+        let reachable = flood_fill(map, player_pos);
+        if reachable.contains(&target) {
+            continue;
+        }
+
+        let distance = |&(x, y): &(i32, i32)| (x - target.0).pow(2) + (y - target.1).pow(2);
+        let closest = reachable.iter().min_by_key(|tile| distance(tile)).cloned().unwrap_or(player_pos);
+        create_l_tunnel(closest, target, map, rng);
+    }
+}
+
+fn reachable_tiles(map: &Map, start: (i32, i32)) -> Vec<Vec<bool>> {
+    let (width, height) = map_size(map);
+    let mut reachable = vec![vec![false; height as usize]; width as usize];
+    for (x, y) in flood_fill(map, start) {
+        reachable[x as usize][y as usize] = true;
+    }
+    reachable
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use tcod::colors;
+    use tile::Tile;
+    use super::*;
+
+    /// Two rooms with no way between them, the player in the first one and
+    /// a potion in the second.
+    fn broken_map() -> (Map, Vec<RefCell<Object>>) {
+        let mut map = vec![vec![Tile::wall(); 20]; 30];
+        for column in &mut map[1..6] {
+            for tile in &mut column[1..6] {
+                *tile = Tile::empty();
+            }
+        }
+        for column in &mut map[20..25] {
+            for tile in &mut column[10..15] {
+                *tile = Tile::empty();
+            }
+        }
+        let player = Object::new(2, 2, '@', "player", colors::WHITE, true);
+        let potion = Object::new(22, 12, '!', "healing potion", colors::VIOLET, false);
+        (map, vec![RefCell::new(player), RefCell::new(potion)])
+    }
+
+    #[test]
+    fn finds_unreachable_objects() {
+        let (map, objects) = broken_map();
+        assert!(!is_valid(&map, &objects));
+        assert_eq!(unreachable_objects(&map, &objects), vec![1]);
+    }
+
+    #[test]
+    fn repair_digs_to_every_object() {
+        let (mut map, objects) = broken_map();
+        let mut rng = StdRng::from_seed(&[1, 2]);
+        repair(&mut map, &objects, &mut rng);
+        assert!(is_valid(&map, &objects));
+    }
+
+    #[test]
+    fn repair_leaves_valid_maps_alone() {
+        let (mut map, objects) = broken_map();
+        create_l_tunnel((5, 5), (20, 10), &mut map, &mut StdRng::from_seed(&[3]));
+        assert!(is_valid(&map, &objects));
+        let blocked = |map: &Map| -> Vec<Vec<bool>> {
+            map.iter().map(|column| column.iter().map(|tile| tile.blocked).collect()).collect()
+        };
+        let before = blocked(&map);
+        repair(&mut map, &objects, &mut StdRng::from_seed(&[1, 2]));
+        assert_eq!(blocked(&map), before);
+    }
+}
@@ -0,0 +1,326 @@
+use std::cell::RefCell;
+use std::mem;
+
+use tcod::colors;
+use tcod::map::{Map as FovMap};
+
+use config::*;
+use map::*;
+use object::*;
+use messages::*;
+use game::*;
+use effects::EffectKind;
+use data::GameData;
+use replay::Recorder;
+
+/// Everything needed to play the game, without any window or console.
+/// Front-ends read it to draw the game and change it only through actions.
+pub struct World {
+    pub object_manager: ObjectsManager,
+    pub game: Game,
+    pub fov: FovMap,
+    pub config: Config,
+    // writes the replay of the game, if it is recorded
+    pub recorder: Option<Recorder>,
+}
+
+/// What the player can do in the game. Targets are chosen by the front-end
+/// before the action is taken.
+#[derive(Clone, Copy, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub enum Action {
+    // move or attack whatever is in the way
+    Move(i32, i32),
+    Wait,
+    PickUp,
+    Drop(usize),
+    // inventory index and the tile it's aimed at, if the item needs one
+    UseItem(usize, Option<(i32, i32)>),
+    Descend,
+    Ascend,
+    LevelUp(LevelUpStat),
+}
+
+impl World {
+    pub fn new(seed: u32, mode: GameMode, data: &GameData) -> Self {
+        let mut player = Object::new(0, 0, '@', "player", colors::WHITE, true);
+        player.alive = true;
+        player.fighter = Some(Fighter{
+            base_max_hp: 100, hp: 100, base_defense: 1, base_power: 2, xp: 0,
+            base_speed: NORMAL_SPEED, energy: ACTION_ENERGY, regen: 0, hit_effect: None,
+            on_death: DeathCallback::Player,
+        });
+        let level = 1;
+
+        let mut objects = vec![RefCell::new(player)];
+
+        let mut game = Game {
+            map: vec![],
+            log: Messages::new(),
+            inventory: vec![],
+            dungeon_level: level,
+            levels: vec![],
+            seed: seed,
+            turn: 0,
+            mode: mode,
+            actions_taken: 0,
+        };
+        let mut rng = game.level_rng();
+        game.map = make_map(&mut objects, level, data, &mut rng);
+
+        // initial equipment
+        let mut dagger = Object::new(0, 0, '-', "dagger", colors::SKY, false);
+        dagger.item = Some(Item::Equipment);
+        dagger.equipment = Some(Equipment {
+            equipped: true,
+            slot: Slot::LeftHand,
+            max_hp_bonus: 0,
+            defense_bonus: 0,
+            power_bonus: 2,
+            speed_bonus: 0,
+        });
+        game.inventory.push(dagger);
+
+        // greeting
+        game.log.add("Welcome stranger! Prepare to die in these catacombs. Hahaha.", colors::RED);
+
+        World::from_saved(objects.into_iter().map(|cell| cell.into_inner()).collect(), game, data.config)
+    }
+
+    /// Puts back together a game that was saved, to be played with the given settings.
+    pub fn from_saved(objects: Vec<Object>, game: Game, config: Config) -> Self {
+        let fov = new_fov(&game.map);
+        let mut world = World {
+            object_manager: ObjectsManager { objects: objects.into_iter().map(RefCell::new).collect() },
+            game: game,
+            fov: fov,
+            config: config,
+            recorder: None,
+        };
+        world.compute_fov();
+        world
+    }
+
+    /// Plays one player action. When it takes the player's turn, the monsters
+    /// then act until the player can act again.
+    pub fn act(&mut self, action: Action, data: &GameData) -> PlayerAction {
+        use self::Action::*;
+
+        if !self.object_manager.objects[PLAYER].borrow().alive {
+            return PlayerAction::DidntTakeTurn;
+        }
+        self.game.actions_taken += 1;
+        let recorded = match self.recorder {
+            Some(ref mut recorder) => recorder.record(action).is_ok(),
+            None => true,
+        };
+        if !recorded {
+            self.recorder = None;
+            self.game.log.add("Could not record the replay, the rest of the game won't be in it.", colors::RED);
+        }
+
+        let player_action = match action {
+            Move(dx, dy) => {
+                self.object_manager.player_move_or_attack(dx, dy, &mut self.game);
+                PlayerAction::TookTurn
+            },
+            Wait => {
+                self.object_manager.spend_energy(PLAYER, WAIT_COST);
+                PlayerAction::TookTurn
+            },
+            PickUp => {
+                let player_pos = self.object_manager.objects[PLAYER].borrow().pos();
+                let item_id = self.object_manager.objects.iter().map(|c| c.borrow()).position(|object| {
+                    object.pos() == player_pos && object.item.is_some()
+                });
+                let picked_up = match item_id {
+                    Some(item_id) => pick_item_up(item_id, &mut self.object_manager, &mut self.game),
+                    None => false,
+                };
+                if picked_up {
+                    self.object_manager.spend_energy(PLAYER, PICK_UP_COST);
+                    PlayerAction::TookTurn
+                } else {
+                    PlayerAction::DidntTakeTurn
+                }
+            },
+            Drop(inventory_id) => {
+                if inventory_id < self.game.inventory.len() {
+                    drop_item(inventory_id, &mut self.object_manager, &mut self.game);
+                    self.object_manager.spend_energy(PLAYER, DROP_COST);
+                    PlayerAction::TookTurn
+                } else {
+                    PlayerAction::DidntTakeTurn
+                }
+            },
+            UseItem(inventory_id, target) => {
+                if inventory_id < self.game.inventory.len()
+                    && use_item(inventory_id, target, &mut self.object_manager, &mut self.game, &self.fov, &self.config) {
+                    self.object_manager.spend_energy(PLAYER, USE_ITEM_COST);
+                    PlayerAction::TookTurn
+                } else {
+                    PlayerAction::DidntTakeTurn
+                }
+            },
+            Descend => {
+                if self.player_stands_on("stairs") {
+                    let level = self.game.dungeon_level + 1;
+                    self.change_level(level, data);
+                    self.object_manager.spend_energy(PLAYER, STAIRS_COST);
+                    PlayerAction::TookTurn
+                } else {
+                    PlayerAction::DidntTakeTurn
+                }
+            },
+            Ascend => {
+                if self.player_stands_on("up stairs") {
+                    let level = self.game.dungeon_level - 1;
+                    self.change_level(level, data);
+                    self.object_manager.spend_energy(PLAYER, STAIRS_COST);
+                    PlayerAction::TookTurn
+                } else {
+                    PlayerAction::DidntTakeTurn
+                }
+            },
+            LevelUp(stat) => {
+                self.level_up(stat);
+                PlayerAction::DidntTakeTurn
+            },
+        };
+
+        self.compute_fov();
+        if player_action == PlayerAction::TookTurn {
+            self.object_manager.advance_time(&mut self.game, &self.fov, &self.config);
+            // effects may have run out or blinded the player meanwhile
+            self.compute_fov();
+        }
+        player_action
+    }
+
+    /// Experience the player needs to reach the next level.
+    pub fn level_up_xp(&self) -> i32 {
+        let player = self.object_manager.objects[PLAYER].borrow();
+        self.config.level_up_base + player.level * self.config.level_up_factor
+    }
+
+    /// The player has enough experience to level up, and must pick a stat to raise.
+    pub fn level_up_pending(&self) -> bool {
+        let xp = self.object_manager.objects[PLAYER].borrow().fighter.as_ref().map_or(0, |f| f.xp);
+        xp >= self.level_up_xp()
+    }
+
+    fn level_up(&mut self, stat: LevelUpStat) {
+        if !self.level_up_pending() {
+            return;
+        }
+        let level_up_xp = self.level_up_xp();
+        let mut player = self.object_manager.objects[PLAYER].borrow_mut();
+        player.level += 1;
+        self.game.log.add(format!("Your battle skills grow stronger! You reached level {}!", player.level), colors::YELLOW);
+
+        let fighter = player.fighter.as_mut().unwrap();
+        fighter.xp -= level_up_xp;
+        match stat {
+            LevelUpStat::Constitution => { fighter.base_max_hp += 20; fighter.hp += 20; },
+            LevelUpStat::Strength => fighter.base_power += 1,
+            LevelUpStat::Agility => fighter.base_defense += 1,
+        }
+    }
+
+    fn player_stands_on(&self, name: &str) -> bool {
+        let player_pos = self.object_manager.objects[PLAYER].borrow().pos();
+        self.object_manager.objects.iter().map(|c| c.borrow()).any(|object| {
+            object.pos() == player_pos && object.name == name
+        })
+    }
+
+    /// Moves the player to another dungeon level. The level being left is stored
+    /// in the game as it is, and a new one is only generated on the first visit.
+    fn change_level(&mut self, new_level: u32, data: &GameData) {
+        let going_down = new_level > self.game.dungeon_level;
+        {
+            let game = &mut self.game;
+            let objects = &mut self.object_manager.objects;
+            // check if player is the first(0) element, store everything else
+            assert_eq!(&objects[PLAYER] as *const _, &objects[0] as *const _);
+            let left_objects = objects.drain(1..).map(|cell| cell.into_inner()).collect();
+            let left_map = mem::replace(&mut game.map, vec![]);
+            game.store_level(Level { map: left_map, objects: left_objects });
+
+            game.dungeon_level = new_level;
+            match game.take_level(new_level) {
+                Some(level) => {
+                    game.map = level.map;
+                    objects.extend(level.objects.into_iter().map(RefCell::new));
+
+                    // arrive on the stairs leading back where we came from
+                    let stairs_name = if going_down { "up stairs" } else { "stairs" };
+                    let stairs_pos = objects.iter().map(|c| c.borrow())
+                        .find(|object| object.name == stairs_name)
+                        .map(|object| object.pos());
+                    if let Some((x, y)) = stairs_pos {
+                        objects[PLAYER].borrow_mut().set_pos(x, y);
+                    }
+
+                    if going_down {
+                        game.log.add(format!("You descend back to level {}.", new_level), colors::RED);
+                    } else {
+                        game.log.add(format!("You climb back up to level {}.", new_level), colors::RED);
+                    }
+                },
+                None => {
+                    game.log.add("You take a moment to rest, and recover your strength.", colors::VIOLET);
+                    {
+                        let mut player = objects[PLAYER].borrow_mut();
+                        let heal_hp = player.max_hp(game) / 2;
+                        player.heal(heal_hp, game);
+                    }
+
+                    game.log.add("After a rare moment of peace, you descend deeper into \
+                                  the heart of the dungeon...", colors::RED);
+
+                    let mut rng = game.level_rng();
+                    game.map = make_map(objects, new_level, data, &mut rng);
+                    let (x, y) = objects[PLAYER].borrow().pos();
+                    place_up_stairs(x, y, objects);
+                },
+            }
+        }
+
+        self.initialise_fov();
+    }
+
+    fn initialise_fov(&mut self) {
+        self.fov = new_fov(&self.game.map);
+        self.compute_fov();
+    }
+
+    /// Recomputes what the player sees, and marks it as explored.
+    pub fn compute_fov(&mut self) {
+        let player = self.object_manager.objects[PLAYER].borrow();
+        let radius = if player.has_effect(EffectKind::Blindness) { 1 } else { self.config.torch_radius };
+        self.fov.compute_fov(player.x, player.y, radius, self.config.fov_light_walls,
+                             self.config.fov_algorithm.algorithm());
+
+        let (width, height) = map_size(&self.game.map);
+        for x in 0..width {
+            for y in 0..height {
+                if self.fov.is_in_fov(x, y) {
+                    self.game.map[x as usize][y as usize].explored = true;
+                }
+            }
+        }
+    }
+}
+
+/// The field of view map of a level, with what can be seen and walked through.
+fn new_fov(map: &Map) -> FovMap {
+    let (width, height) = map_size(map);
+    let mut fov = FovMap::new(width, height);
+    for x in 0..width {
+        for y in 0..height {
+            let tile = &map[x as usize][y as usize];
+            fov.set(x, y, !tile.block_sight, !tile.blocked)
+        }
+    }
+    fov
+}